@@ -0,0 +1,332 @@
+use bencher::{benchmark_group, benchmark_main, Bencher};
+use rand::Rng;
+use std::path::Path;
+use bvh::math::vector::*;
+use bvh::triangle::Triangle;
+use bvh::mesh::TriangleMesh;
+use bvh::bvh::{BVH, BuildOptions, Split};
+use bvh::shape::*;
+use bvh::ray::Ray;
+use bvh::aabb::AABB;
+
+fn obj(path: &Path) -> Vec<Box<dyn Shape>> {
+  let (models, _) = tobj::load_obj(path).unwrap();
+  let mut instances: Vec<Box<dyn Shape>> = Vec::with_capacity(
+    models.iter().map( |m| m.mesh.indices.len() / 3).sum()
+  );
+  for m in models {
+    let mesh = &m.mesh;
+    // println!("{}: {} ploygon", m.name, mesh.indices.len() / 3);
+    for f in 0..mesh.indices.len() / 3 {
+      let mut polygon = [Vector3::zero(); 3];
+      for (i, p) in polygon.iter_mut().enumerate() {
+        let index: usize = f * 3 + i;
+        *p = Vector3::new(
+          mesh.positions[mesh.indices[index] as usize * 3] * 100.0,
+          mesh.positions[mesh.indices[index] as usize * 3 + 1] * 100.0,
+          mesh.positions[mesh.indices[index] as usize * 3 + 2] * 100.0,
+        );
+      }
+      instances.push(Box::new(Triangle::new(polygon[0], polygon[1], polygon[2])));
+    }
+  }
+  println!("{} triangles", instances.len());
+  instances
+}
+
+fn obj_mesh(path: &Path) -> TriangleMesh {
+  let (models, _) = tobj::load_obj(path).unwrap();
+  let has_normals = models.iter().all( |m| !m.mesh.normals.is_empty() );
+  let has_texcoords = models.iter().all( |m| !m.mesh.texcoords.is_empty() );
+  let mut positions = Vec::new();
+  let mut normals = Vec::new();
+  let mut texcoords = Vec::new();
+  let mut indices = Vec::new();
+  for m in models {
+    let mesh = &m.mesh;
+    let base = positions.len() as u32;
+    positions.extend(mesh.positions.chunks(3).map( |p| {
+      Vector3::new(p[0] * 100.0, p[1] * 100.0, p[2] * 100.0)
+    }));
+    normals.extend(mesh.normals.chunks(3).map( |n| Vector3::new(n[0], n[1], n[2]) ));
+    texcoords.extend(mesh.texcoords.chunks(2).map( |c| (c[0], c[1]) ));
+    indices.extend(mesh.indices.iter().map( |i| base + i ));
+  }
+  println!("{} triangles", indices.len() / 3);
+  let mut mesh = TriangleMesh::new(positions, indices);
+  if has_normals {
+    mesh = mesh.with_normals(normals);
+  }
+  if has_texcoords {
+    mesh = mesh.with_texcoords(texcoords);
+  }
+  mesh
+}
+
+fn random_ray_in_aabb<R>(aabb: &AABB, count: usize, mut rng: R) -> Vec<Ray>
+  where
+    R: Rng,
+{
+  let diagnal = (aabb.max - aabb.min).norm();
+  (0..count).map( |_| {
+    let from: Vector3 = (0..3).map( |i|
+      (aabb.max[i] - aabb.min[i]) * rng.gen_range(-1.0f32, 1.0)
+    ).collect::<Vec<_>>().into();
+    let to: Vector3 = (0..3).map( |i|
+      (aabb.max[i] - aabb.min[i]) * rng.gen_range(-1.0f32, 1.0)
+    ).collect::<Vec<_>>().into();
+    let direction = (to - from).normalize();
+    let origin = from - direction * diagnal;
+    Ray::new(origin, direction)
+  }).collect()
+}
+
+fn bench_construct_bvh(b: &mut Bencher) {
+  println!();
+  let objects = obj(Path::new("models/bunny/bunny.obj"));
+  println!("cost {}", BVH::new(&objects).cost());
+  b.iter( || {
+    BVH::new(&objects);
+  })
+}
+
+fn bench_construct_bvh_presorted(b: &mut Bencher) {
+  println!();
+  let objects = obj(Path::new("models/bunny/bunny.obj"));
+  let options = BuildOptions {
+    split: Split::PresortedSweep,
+    ..Default::default()
+  };
+  println!("cost {}", BVH::with_options(&objects, options).cost());
+  b.iter( || {
+    BVH::with_options(&objects, options);
+  })
+}
+
+fn bench_construct_bvh_binned(b: &mut Bencher) {
+  println!();
+  let objects = obj(Path::new("models/bunny/bunny.obj"));
+  let options = BuildOptions {
+    split: Split::Binned(16),
+    ..Default::default()
+  };
+  println!("cost {}", BVH::with_options(&objects, options).cost());
+  b.iter( || {
+    BVH::with_options(&objects, options);
+  })
+}
+
+fn bench_construct_bvh_morton(b: &mut Bencher) {
+  println!();
+  let objects = obj(Path::new("models/bunny/bunny.obj"));
+  let options = BuildOptions {
+    split: Split::Morton,
+    ..Default::default()
+  };
+  println!("cost {}", BVH::with_options(&objects, options).cost());
+  b.iter( || {
+    BVH::with_options(&objects, options);
+  })
+}
+
+fn bench_construct_bvh_ploc(b: &mut Bencher) {
+  println!();
+  let objects = obj(Path::new("models/bunny/bunny.obj"));
+  let options = BuildOptions {
+    split: Split::Ploc(16),
+    ..Default::default()
+  };
+  println!("cost {}", BVH::with_options(&objects, options).cost());
+  b.iter( || {
+    BVH::with_options(&objects, options);
+  })
+}
+
+fn bench_intersection_bvh(b: &mut Bencher) {
+  println!();
+  let objects = obj(Path::new("models/sponza/sponza.obj"));
+  let bvh = BVH::new(&objects);
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let random_rays = random_ray_in_aabb(bvh.aabb(), 10000, &mut rng);
+  b.iter( || {
+    for ray in &random_rays {
+      bvh.intersect(ray);
+    }
+  });
+}
+
+fn bench_intersection_bvh_binned(b: &mut Bencher) {
+  println!();
+  let objects = obj(Path::new("models/sponza/sponza.obj"));
+  let bvh = BVH::with_options(&objects, BuildOptions {
+    split: Split::Binned(16),
+    ..Default::default()
+  });
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let random_rays = random_ray_in_aabb(bvh.aabb(), 10000, &mut rng);
+  b.iter( || {
+    for ray in &random_rays {
+      bvh.intersect(ray);
+    }
+  });
+}
+
+fn bench_intersection_bvh_morton(b: &mut Bencher) {
+  println!();
+  let objects = obj(Path::new("models/sponza/sponza.obj"));
+  let bvh = BVH::with_options(&objects, BuildOptions {
+    split: Split::Morton,
+    ..Default::default()
+  });
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let random_rays = random_ray_in_aabb(bvh.aabb(), 10000, &mut rng);
+  b.iter( || {
+    for ray in &random_rays {
+      bvh.intersect(ray);
+    }
+  });
+}
+
+fn bench_intersection_bvh_sbvh(b: &mut Bencher) {
+  println!();
+  let objects = obj(Path::new("models/sponza/sponza.obj"));
+  let bvh = BVH::with_options(&objects, BuildOptions {
+    split: Split::Sbvh { budget: 0.3 },
+    ..Default::default()
+  });
+  println!("duplicates {}", bvh.duplicates());
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let random_rays = random_ray_in_aabb(bvh.aabb(), 10000, &mut rng);
+  b.iter( || {
+    for ray in &random_rays {
+      bvh.intersect(ray);
+    }
+  });
+}
+
+fn bench_intersection_mesh(b: &mut Bencher) {
+  println!();
+  let mesh = obj_mesh(Path::new("models/sponza/sponza.obj"));
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let random_rays = random_ray_in_aabb(mesh.aabb(), 10000, &mut rng);
+  b.iter( || {
+    for ray in &random_rays {
+      mesh.intersect(ray);
+    }
+  });
+}
+
+#[cfg(feature = "svenstaro_bvh")]
+mod svenstaro {
+  use super::*;
+  use svenstaro_bvh::aabb::Bounded;
+
+  struct SvenstaroTriangle<'a> {
+    index: usize,
+    triangle: &'a Box<dyn Shape>,
+  }
+
+  impl<'a> svenstaro_bvh::aabb::Bounded for SvenstaroTriangle<'a> {
+    fn aabb(&self) -> svenstaro_bvh::aabb::AABB {
+      let aabb = self.triangle.aabb();
+      let min = convert_nalgebra_point(aabb.min);
+      let max = convert_nalgebra_point(aabb.max);
+      svenstaro_bvh::aabb::AABB::with_bounds(min, max)
+    }
+  }
+
+  impl<'a> svenstaro_bvh::bounding_hierarchy::BHShape for SvenstaroTriangle<'a> {
+    fn set_bh_node_index(&mut self, index: usize) {
+      self.index = index;
+    }
+
+    fn bh_node_index(&self) -> usize {
+      self.index
+    }
+  }
+
+  fn convert_nalgebra_vector(v: Vector3) -> svenstaro_bvh::nalgebra::Vector3<f32> {
+    svenstaro_bvh::nalgebra::Vector3::new(v.x, v.y, v.z)
+  }
+
+  fn convert_nalgebra_point(v: Vector3) -> svenstaro_bvh::nalgebra::Point3<f32> {
+    svenstaro_bvh::nalgebra::Point3::new(v.x, v.y, v.z)
+  }
+
+  fn convert_point(v: svenstaro_bvh::nalgebra::Point3<f32>) -> Vector3 {
+    Vector3::new(v.x, v.y, v.z)
+  }
+
+  pub fn bench_intersection_svenstaro_bvh(b: &mut Bencher) {
+    println!();
+    let obj = obj(&Path::new("models/sponza/sponza.obj"));
+    let mut objects = obj.iter().enumerate().map( |(i, v)|
+      SvenstaroTriangle { index: i, triangle: v }
+    ).collect::<Vec<_>>();
+    let mut bounds = svenstaro_bvh::aabb::AABB::empty();
+    for triangle in &objects {
+      bounds.join_mut(&triangle.aabb());
+    }
+    let bvh = svenstaro_bvh::bvh::BVH::build(objects.as_mut_slice());
+    let mut rng = rand::XorShiftRng::new_unseeded();
+    let min = convert_point(bounds.min);
+    let max = convert_point(bounds.max);
+    let aabb = AABB {
+      min: min,
+      max: max,
+      center: min + max / 2.0,
+    };
+    let rays = random_ray_in_aabb(&aabb, 10000, &mut rng);
+    let random_rays = rays.iter().map( |ray| {
+      let origin = convert_nalgebra_point(ray.origin);
+      let direction = convert_nalgebra_vector(ray.direction);
+      (svenstaro_bvh::ray::Ray::new(origin, direction), ray)
+    }).collect::<Vec<_>>();
+    b.iter( || {
+      for &(ref ray, org_ray) in &random_rays {
+        let shapes = bvh.traverse(&ray, &objects);
+        shapes.iter().flat_map(|v| v.triangle.intersect(&org_ray)).min_by(
+          |a, b| {
+            a.distance.partial_cmp(&b.distance).unwrap()
+          },
+        );
+      }
+    });
+  }
+}
+
+// #[bench]
+// fn bench_intersection_brute_force(b: &mut Bencher) {
+//   println!();
+//   let objects = obj(&Path::new("models/monkey/monkey.obj"));
+//   let aabb_list = objects.iter().map( |v| v.aabb() ).collect::<Vec<_>>();
+//   let aabb = AABB::merge(&aabb_list.iter().collect());
+//   b.iter( || {
+//     let mut rng = rand::XorShiftRng::new_unseeded();
+//     random_ray_in_aabb(&aabb, 1000, &mut rng, |ray| {
+//       brute_force(&objects, &ray);
+//     });
+//   })
+// }
+
+benchmark_group!(
+  benches,
+  bench_construct_bvh,
+  bench_construct_bvh_presorted,
+  bench_construct_bvh_binned,
+  bench_construct_bvh_morton,
+  bench_construct_bvh_ploc,
+  bench_intersection_bvh,
+  bench_intersection_bvh_binned,
+  bench_intersection_bvh_morton,
+  bench_intersection_bvh_sbvh,
+  bench_intersection_mesh
+);
+
+#[cfg(feature = "svenstaro_bvh")]
+benchmark_group!(svenstaro_benches, svenstaro::bench_intersection_svenstaro_bvh);
+
+#[cfg(not(feature = "svenstaro_bvh"))]
+benchmark_main!(benches);
+#[cfg(feature = "svenstaro_bvh")]
+benchmark_main!(benches, svenstaro_benches);
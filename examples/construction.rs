@@ -0,0 +1,38 @@
+use bvh::bvh::{BVH, BuildOptions, Split};
+use bvh::shape::*;
+use bvh::triangle::Triangle;
+use bvh::math::vector::*;
+use rand::Rng;
+use std::time::{Duration, Instant};
+
+// モデルを使わずにランダムな三角形で構築時間を比べる (1スレッド)
+fn main() {
+  let count = 200000;
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let mut point = |range: f32| Vector3::new(
+    rng.gen_range(-range, range),
+    rng.gen_range(-range, range),
+    rng.gen_range(-range, range),
+  );
+  let objects = (0..count).map( |_| {
+    let p0 = point(10.0);
+    let p1 = p0 + point(1.0);
+    let p2 = p0 + point(1.0);
+    Box::new(Triangle::new(p0, p1, p2)) as Box<dyn Shape>
+  }).collect::<Vec<_>>();
+  println!("{} triangles", count);
+  for &(name, split) in [("sweep", Split::Sweep), ("presorted", Split::PresortedSweep)].iter() {
+    let options = BuildOptions {
+      split,
+      parallel: false,
+      ..Default::default()
+    };
+    // 3回のうち最も速いもの
+    let elapsed = (0..3).map( |_| {
+      let start_time = Instant::now();
+      BVH::with_options(&objects, options);
+      start_time.elapsed()
+    }).min().unwrap_or(Duration::ZERO);
+    println!("construction ({}): {}ms", name, elapsed.as_millis());
+  }
+}
@@ -1,10 +1,7 @@
-extern crate ordered_float;
-
 use std::f32;
-use math::vector::*;
-use ray::Ray;
-use self::ordered_float::OrderedFloat;
-use constant::*;
+use crate::math::vector::*;
+use crate::ray::Ray;
+use ordered_float::OrderedFloat;
 
 #[derive(Clone)]
 pub struct AABB {
@@ -39,8 +36,8 @@ impl AABB {
       *list.iter().map(|v| OrderedFloat(v.max.z)).max().unwrap(),
     );
     AABB {
-      min: min,
-      max: max,
+      min,
+      max,
       center: (min + max) / 2.0,
     }
   }
@@ -57,12 +54,57 @@ impl AABB {
       self.max.z.max(v.max.z),
     );
     AABB {
-      min: min,
-      max: max,
+      min,
+      max,
+      center: (min + max) / 2.0,
+    }
+  }
+
+  // 共通部分 (重ならなければ min > max となる軸がある)
+  pub fn overlap_with(&self, v: &AABB) -> AABB {
+    let min = Vector3::new(
+      self.min.x.max(v.min.x),
+      self.min.y.max(v.min.y),
+      self.min.z.max(v.min.z),
+    );
+    let max = Vector3::new(
+      self.max.x.min(v.max.x),
+      self.max.y.min(v.max.y),
+      self.max.z.min(v.max.z),
+    );
+    AABB {
+      min,
+      max,
       center: (min + max) / 2.0,
     }
   }
 
+  // 中身を持たない (いずれかの軸で min > max)
+  pub fn is_empty(&self) -> bool {
+    (0..3).any( |i| self.min[i] > self.max[i] )
+  }
+
+  // axis 軸に垂直な position の平面で切り分けた両側
+  pub fn split(&self, axis: usize, position: f32) -> (AABB, AABB) {
+    let position = position.max(self.min[axis]).min(self.max[axis]);
+    let replace = |v: Vector3| Vector3::new(
+      if axis == 0 { position } else { v.x },
+      if axis == 1 { position } else { v.y },
+      if axis == 2 { position } else { v.z },
+    );
+    let left = AABB {
+      min: self.min,
+      max: replace(self.max),
+      center: (self.min + replace(self.max)) / 2.0,
+    };
+    let right = AABB {
+      min: replace(self.min),
+      max: self.max,
+      center: (replace(self.min) + self.max) / 2.0,
+    };
+    (left, right)
+  }
+
   pub fn empty() -> AABB {
     AABB {
       min: Vector3::new(f32::INFINITY, f32::INFINITY, f32::INFINITY),
@@ -73,8 +115,14 @@ impl AABB {
 
   #[inline]
   pub fn is_intersect(&self, ray: &Ray) -> bool {
-    let mut min = -INF;
-    let mut max = INF;
+    self.intersect_distance(ray).is_some()
+  }
+
+  // スラブ法で求めたレイの範囲内での進入距離
+  #[inline]
+  pub fn intersect_distance(&self, ray: &Ray) -> Option<f32> {
+    let mut min = ray.t_min;
+    let mut max = ray.t_max;
     for i in 0..3 {
       let inv_d = 1.0 / ray.direction[i];
       let t1 = (self.min[i] - ray.origin[i]) * inv_d;
@@ -86,8 +134,8 @@ impl AABB {
       if max > t_max {
         max = t_max
       }
-      if min > max { return false }
+      if min > max { return None }
     }
-    true
+    Some(min)
   }
 }
@@ -0,0 +1,189 @@
+use bvh::math::vector::*;
+use bvh::triangle::Triangle;
+use bvh::bvh::{BVH, BuildOptions, Split};
+use bvh::shape::*;
+use bvh::ray::Ray;
+use std::path::Path;
+use bvh::aabb::AABB;
+// use bvh::constant::EPS;
+
+fn main() {
+  let split: usize = 100;
+  // let mut objects = obj(&Path::new("models/happy_vrip/buddha.obj"));
+  let objects = obj(Path::new("models/happy_vrip/buddha.obj"));
+  // let objects = obj(&Path::new("models/monkey/monkey.obj"));
+  println!("obj loaded");
+  // let objects_ = obj(&path);
+  // 構築方法は引数で選択 (sweep, presorted, binned, morton, ploc, sbvh, spatial_median, object_median)
+  let method = std::env::args().nth(1).unwrap_or_else( || "sweep".to_string() );
+  let options = BuildOptions {
+    split: split_by_name(&method),
+    ..Default::default()
+  };
+  let start_time = time::now();
+  let bvh = BVH::with_options(&objects, options);
+  let end_time = time::now();
+  let elapse_time = (end_time - start_time).num_microseconds().unwrap();
+  println!("construction ({}): {}us", method, elapse_time);
+  println!("cost {}", bvh.cost());
+  println!("min {}", bvh.aabb().min);
+  println!("max {}", bvh.aabb().max);
+  println!("center {}", bvh.aabb().center);
+  let pre = (0..split).flat_map( |i|
+    (0..split).map( |j|
+      get_ray_in_aabb(i, j, split - 1, bvh.aabb())
+    ).collect::<Vec<_>>()
+  ).collect::<Vec<_>>();
+  // let intersect_count = pre.iter().flat_map( |ray| bvh.intersect(&ray) ).count();
+  // println!("intersect count {}", intersect_count);
+  // for ray in &pre {
+  //   let bvh_i = bvh.intersect(&ray);
+  //   let bf_i = brute_force(&objects_, &ray);
+  //   if bvh_i.is_some() != bf_i.is_some() {
+  //       panic!("Fail: no intersect")
+  //   }
+  //   if bvh_i.is_some() && bf_i.is_some() {
+  //     if (bvh_i.unwrap().position - bf_i.unwrap().position).norm() > EPS {
+  //       panic!("Fail: wrong position")
+  //     }
+  //   }
+  // }
+  // for ray in &pre {
+  //   bvh.intersect(&ray);
+  // }
+  // panic!();
+  let test_ray = get_ray_in_aabb(76, 48, split - 1, bvh.aabb());
+  let test_intersect = bvh.intersect(&test_ray);
+  println!("test ray origin: {} direction: {}", test_ray.origin, test_ray.direction);
+  println!("test intersect coordinate {}", test_intersect.unwrap().position);
+  let results = (0..10).map( |i| {
+    let start_time = time::now();
+    for ray in &pre {
+      bvh.intersect(ray);
+    }
+    let end_time = time::now();
+    let elapse_time = (end_time - start_time).num_microseconds().unwrap();
+    println!(
+      "try {} | elapse: {}us",
+      i,
+      elapse_time, 
+    );
+    elapse_time as f64
+  }).collect::<Vec<_>>();
+  let n = results.len() as f64;
+  let ave = results.iter().sum::<f64>() / n;
+  println!("sample size: {}", n);
+  println!("average: {}", ave);
+  let sigma = (results.iter().map( |v| (v - ave).powi(2) ).sum::<f64>() / (n * (n - 1.0))).sqrt();
+  println!("standard error: {}", sigma);
+}
+
+fn split_by_name(name: &str) -> Split {
+  match name {
+    "sweep" => Split::Sweep,
+    "presorted" => Split::PresortedSweep,
+    "binned" => Split::Binned(16),
+    "morton" => Split::Morton,
+    "ploc" => Split::Ploc(16),
+    "sbvh" => Split::Sbvh { budget: 0.3 },
+    "spatial_median" => Split::SpatialMedian,
+    "object_median" => Split::ObjectMedian,
+    _ => panic!("Unknown build method: {}", name),
+  }
+}
+
+fn get_ray_in_aabb(i: usize, j: usize, split: usize, aabb: &AABB) -> Ray {
+  let x = aabb.min.x + (aabb.max.x - aabb.min.x) * (i as f32 / split as f32);
+  let y = aabb.min.y + (aabb.max.y - aabb.min.y) * (j as f32 / split as f32);
+  let z = aabb.min.z;
+  let origin = Vector3::new(x, y, z);
+  let direction = (aabb.center - origin).normalize();
+  Ray::new(origin, direction)
+}
+
+fn obj(path: &Path) -> Vec<Box<dyn Shape>> {
+  let (models, _) = tobj::load_obj(path).unwrap();
+  let mut instances: Vec<Box<dyn Shape>> = Vec::with_capacity(
+    models.iter().map( |m| m.mesh.indices.len() / 3).sum()
+  );
+  for m in models {
+    let mesh = &m.mesh;
+    // println!("{}: {} ploygon", m.name, mesh.indices.len() / 3);
+    for f in 0..mesh.indices.len() / 3 {
+      let mut polygon = [Vector3::zero(); 3];
+      for (i, p) in polygon.iter_mut().enumerate() {
+        let index: usize = f * 3 + i;
+        *p = Vector3::new(
+          mesh.positions[mesh.indices[index] as usize * 3] * 100.0,
+          mesh.positions[mesh.indices[index] as usize * 3 + 1] * 100.0,
+          mesh.positions[mesh.indices[index] as usize * 3 + 2] * 100.0,
+        );
+      }
+      instances.push(Box::new(Triangle::new(polygon[0], polygon[1], polygon[2])));
+    }
+  }
+  println!("{} triangles", instances.len());
+  instances
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use bvh::intersection::Intersection;
+  use bvh::constant::*;
+  use rand::Rng;
+
+  #[test]
+  // モデルは同梱していないので明示したときだけ実行する
+  #[ignore]
+  fn correct() {
+    let objects = obj(Path::new("models/monkey/monkey.obj"));
+    let objects_ = obj(Path::new("models/monkey/monkey.obj"));
+    let bvh = BVH::new(&objects);
+    let mut rng = rand::XorShiftRng::new_unseeded();
+    for _ in 0..10000 {
+      let origin = Vector3::new(
+        rng.gen_range(-10.0f32, 10.0),
+        rng.gen_range(-10.0f32, 10.0),
+        rng.gen_range(-10.0f32, 10.0),
+      );
+      let direction = Vector3::new(
+        rng.gen_range(-1.0f32, 1.0),
+        rng.gen_range(-1.0f32, 1.0),
+        rng.gen_range(-1.0f32, 1.0),
+      );
+      let ray = Ray::new(origin, direction.normalize());
+      let i1 = brute_force(&objects_, &ray);
+      let i2 = bvh.intersect(&ray);
+      if i1.is_some() != i2.is_some() {
+        println!("origin {:?}", origin);
+        println!("direction {:?}", direction);
+        if let Some(v) = i1 {
+          println!("correct {:?}", v.position)
+        }
+        if let Some(v) = i2 {
+          println!("test {:?}", v.position)
+        }
+        panic!();
+      } else if let Some(v) = i1 {
+        assert!((v.position - i2.unwrap().position).norm() < EPS);
+      }
+    }
+  }
+
+  #[test]
+  fn split_names() {
+    // main で選べる構築方法の名前が全て解決できる
+    for name in ["sweep", "presorted", "binned", "morton", "ploc", "sbvh", "spatial_median", "object_median"].iter() {
+      split_by_name(name);
+    }
+  }
+
+  fn brute_force(objects: &Vec<Box<dyn Shape>>, ray: &Ray) -> Option<Intersection> {
+    objects.iter().flat_map(|v| v.intersect(ray)).min_by(
+      |a, b| {
+        a.distance.partial_cmp(&b.distance).unwrap()
+      },
+    )
+  }
+}
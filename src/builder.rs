@@ -0,0 +1,914 @@
+use crate::aabb::AABB;
+use crate::bvh::BuildOptions;
+use ordered_float::OrderedFloat;
+use std::f32;
+use std::thread;
+
+// 構築の対象となるプリミティブ (元の並びでのインデックスを持つ)
+#[derive(Clone)]
+pub struct Primitive {
+  pub aabb: AABB,
+  pub index: usize,
+  // 重心のモートン符号 (Morton と Ploc でのみ使用)
+  code: u32,
+}
+
+// 深さ優先に並んだ木のノード (探索はこの表現にのみ依存する)
+#[derive(Clone)]
+pub struct Node {
+  pub aabb: AABB,
+  // 節なら右の子のインデックス (左の子は直後)、葉ならプリミティブ列の先頭
+  pub offset: usize,
+  // 葉に含まれるプリミティブ数 (節は0)
+  pub count: usize,
+}
+
+// AABB の並びから木を作る方法
+pub trait Builder {
+  // 深さ優先に並んだノードと、葉から参照されるプリミティブのインデックス列を返す
+  fn build(&self, aabbs: Vec<AABB>, options: &BuildOptions) -> (Vec<Node>, Vec<usize>);
+}
+
+// 上から順に二分していく構築での分割方法
+pub trait Partition: Sync {
+  // 構築を始める前に全体に対して一度だけ呼ばれる
+  fn prepare(&self, _list: &mut [Primitive]) {}
+
+  // 分割位置で二分されるように list を並べ替え、全体のAABBと分割位置、分割したときのSAHコストを返す
+  // SAHを評価しない分割方法はコストを f32::INFINITY とし、葉にするかは max_leaf_size だけで決まる
+  fn partition(&self, list: &mut [Primitive], options: &BuildOptions) -> (AABB, usize, f32);
+}
+
+// Partition による分割を再帰的に繰り返す構築
+pub struct TopDown<P: Partition>(pub P);
+
+impl<P: Partition> Builder for TopDown<P> {
+  fn build(&self, aabbs: Vec<AABB>, options: &BuildOptions) -> (Vec<Node>, Vec<usize>) {
+    let mut list = primitives(aabbs);
+    self.0.prepare(&mut list);
+    let mut nodes = Vec::with_capacity(2 * list.len());
+    self.construct(&mut list, 0, 0, fork_depth(), options, &mut nodes);
+    (nodes, list.iter().map( |v| v.index ).collect())
+  }
+}
+
+impl<P: Partition> TopDown<P> {
+  // forks は残りの部分木を別スレッドに分けてよい段数
+  fn construct(
+    &self,
+    list: &mut [Primitive],
+    offset: usize,
+    depth: usize,
+    forks: usize,
+    options: &BuildOptions,
+    nodes: &mut Vec<Node>,
+  ) {
+    // セットアップ
+    let n = list.len();
+    // スレッドを分け終えた部分木では軸ごとの評価も1スレッドで行う
+    let serial = BuildOptions {
+      parallel: false,
+      ..*options
+    };
+    let options = if forks > 0 { options } else { &serial };
+    // 要素が1つ以下、または深さの上限に達したときは葉
+    if n <= 1 || depth >= options.max_depth {
+      return leaf(list, offset, nodes);
+    }
+    // 分割位置で二分されるように並べ替え
+    let (aabb, partition_index, t) = self.0.partition(list, options);
+    // 分割しても全て交差判定するより安くならなければ葉
+    if n <= options.max_leaf_size && n as f32 * options.intersection_cost <= t {
+      return leaf(list, offset, nodes);
+    }
+    // 再帰的に子要素を生成
+    assert!(partition_index != 0 && partition_index != n, "Partition must leave primitives on both sides.");
+    // 深さ優先で配置し、右の子の位置は左の部分木を生成した後に確定
+    let index = nodes.len();
+    nodes.push(Node {
+      aabb,
+      offset: 0,
+      count: 0,
+    });
+    let (left, right) = list.split_at_mut(partition_index);
+    if !is_parallel(n, options) {
+      self.construct(left, offset, depth + 1, forks, options, nodes);
+      nodes[index].offset = nodes.len();
+      self.construct(right, offset + partition_index, depth + 1, forks, options, nodes);
+      return;
+    }
+    // 右の部分木は別スレッドで別の列に生成し、左の部分木の後ろに連結する
+    let right_nodes = thread::scope( |s| {
+      let handle = s.spawn( || {
+        let mut right_nodes = Vec::with_capacity(2 * right.len());
+        self.construct(right, offset + partition_index, depth + 1, forks - 1, options, &mut right_nodes);
+        right_nodes
+      });
+      self.construct(left, offset, depth + 1, forks - 1, options, nodes);
+      handle.join().unwrap()
+    });
+    let base = nodes.len();
+    nodes[index].offset = base;
+    nodes.extend(right_nodes.into_iter().map( |mut node| {
+      if node.count == 0 {
+        node.offset += base;
+      }
+      node
+    }));
+  }
+}
+
+// 全ての分割位置でSAHを評価
+pub struct SweepSah;
+
+impl Partition for SweepSah {
+  fn partition(&self, list: &mut [Primitive], options: &BuildOptions) -> (AABB, usize, f32) {
+    // SAHに基づいた最良の分割軸とインデックスを取得 (大きなノードでは軸ごとに並列に評価)
+    let results = if is_parallel(list.len(), options) {
+      let list = &*list;
+      thread::scope( |s| {
+        let handles = (0..3).map( |axis| {
+          s.spawn(move || {
+            // プリミティブの列は複製せず、軸ごとの並びをインデックスの順列で表す
+            let mut order = (0..list.len()).collect::<Vec<_>>();
+            order.sort_unstable_by_key( |&i| (OrderedFloat(list[i].aabb.center[axis]), list[i].index) );
+            sweep_sorted(order.len(), |i| &list[order[i]].aabb, &mut Vec::with_capacity(list.len()), options)
+          })
+        }).collect::<Vec<_>>();
+        handles.into_iter().map( |h| h.join().unwrap() ).collect::<Vec<_>>()
+      })
+    } else {
+      (0..3).map( |axis| sweep_axis(list, axis, options) ).collect::<Vec<_>>()
+    };
+    let (partition_axis, (aabb, partition_index, t)) = results.into_iter().enumerate().min_by_key( |&(_, (_, _, t))| {
+      OrderedFloat(t)
+    }).unwrap();
+    // 基準の軸でソート
+    sort_axis(list, partition_axis);
+    (aabb, partition_index, t)
+  }
+}
+
+// 重心を指定数のビンに分け、ビン境界でのみSAHを評価
+pub struct BinnedSah(pub usize);
+
+impl Partition for BinnedSah {
+  fn partition(&self, list: &mut [Primitive], options: &BuildOptions) -> (AABB, usize, f32) {
+    let bins = self.0;
+    assert!(bins >= 2, "Binned split needs at least 2 bins.");
+    let n = list.len();
+    // 全体のAABBと重心の範囲
+    let mut aabb = AABB::empty();
+    let mut centroid = AABB::empty();
+    for v in list.iter() {
+      aabb = aabb.merge_with(&v.aabb);
+      centroid = centroid.merge_with(&AABB {
+        min: v.aabb.center,
+        max: v.aabb.center,
+        center: v.aabb.center,
+      });
+    }
+    let s_a = aabb.surface_area();
+    let bin = |v: &Primitive, axis: usize| {
+      let extent = centroid.max[axis] - centroid.min[axis];
+      let b = ((v.aabb.center[axis] - centroid.min[axis]) / extent * bins as f32) as usize;
+      b.min(bins - 1)
+    };
+    // SAHに基づいた最良の分割軸とビン境界を取得
+    let mut best: Option<(usize, usize, f32)> = None;
+    for axis in 0..3 {
+      // 重心が一点に集まる軸は分割できない
+      if centroid.max[axis] <= centroid.min[axis] {
+        continue;
+      }
+      // ビンごとのAABBとポリゴン数
+      let mut bin_aabb = vec![AABB::empty(); bins];
+      let mut bin_n = vec![0; bins];
+      for v in list.iter() {
+        let b = bin(v, axis);
+        bin_aabb[b] = bin_aabb[b].merge_with(&v.aabb);
+        bin_n[b] += 1;
+      }
+      // S1のAABBの表面積とポリゴン数
+      let mut s1_aabb = AABB::empty();
+      let mut s1_n = 0;
+      let mut s1 = Vec::with_capacity(bins - 1);
+      for b in 0..bins - 1 {
+        s1_aabb = s1_aabb.merge_with(&bin_aabb[b]);
+        s1_n += bin_n[b];
+        s1.push((s1_aabb.surface_area(), s1_n));
+      }
+      // S2を右から広げながらSAHのスコアを評価
+      let mut s2_aabb = AABB::empty();
+      let mut s2_n = 0;
+      for b in (1..bins).rev() {
+        s2_aabb = s2_aabb.merge_with(&bin_aabb[b]);
+        s2_n += bin_n[b];
+        let (s1_a, s1_n) = s1[b - 1];
+        if s1_n == 0 || s2_n == 0 {
+          continue;
+        }
+        let s2_a = s2_aabb.surface_area();
+        let t = 2.0 * options.traversal_cost +
+          (s1_a * s1_n as f32 + s2_a * s2_n as f32) * options.intersection_cost / s_a;
+        if best.is_none_or(|(_, _, best_t)| t < best_t) {
+          best = Some((axis, b, t));
+        }
+      }
+    }
+    match best {
+      Some((axis, b, t)) => {
+        // ビン境界より左のものを前方に集める
+        let mut partition_index = 0;
+        for i in 0..n {
+          if bin(&list[i], axis) < b {
+            list.swap(partition_index, i);
+            partition_index += 1;
+          }
+        }
+        (aabb, partition_index, t)
+      },
+      // 重心が全て一致する場合は半分に分割
+      None => (aabb, n / 2, f32::INFINITY),
+    }
+  }
+}
+
+// 重心のモートン符号で一度だけソートし、符号の最上位の異なるビットで分割 (LBVH)
+pub struct Morton;
+
+impl Partition for Morton {
+  fn prepare(&self, list: &mut [Primitive]) {
+    sort_morton(list);
+  }
+
+  // 符号順に並んだ列を、先頭と末尾の符号が最初に異なるビットで二分
+  fn partition(&self, list: &mut [Primitive], _options: &BuildOptions) -> (AABB, usize, f32) {
+    let n = list.len();
+    let aabb = list.iter().fold(AABB::empty(), |a, v| a.merge_with(&v.aabb));
+    let first = list[0].code;
+    let last = list[n - 1].code;
+    // 符号が全て一致する場合は半分に分割
+    if first == last {
+      return (aabb, n / 2, f32::INFINITY);
+    }
+    let prefix = (first ^ last).leading_zeros();
+    let partition_index = list.partition_point( |v| (v.code ^ first).leading_zeros() > prefix );
+    (aabb, partition_index, f32::INFINITY)
+  }
+}
+
+// SweepSah と同じ木を作るが、各軸の重心順の列を最初に一度だけ作っておき
+// 分割のたびに3つの列を安定に振り分けることでノードごとのソートを省く
+pub struct PresortedSah;
+
+impl Builder for PresortedSah {
+  fn build(&self, aabbs: Vec<AABB>, options: &BuildOptions) -> (Vec<Node>, Vec<usize>) {
+    let n = aabbs.len();
+    let list = primitives(aabbs);
+    let order = |axis: usize| {
+      let mut sorted = list.clone();
+      sort_axis(&mut sorted, axis);
+      sorted
+    };
+    let mut state = Presorted {
+      orders: [order(0), order(1), order(2)],
+      left: vec![false; n],
+      scratch: Vec::with_capacity(n),
+      areas: Vec::with_capacity(n),
+      indices: vec![0; n],
+      nodes: Vec::with_capacity(2 * n),
+    };
+    state.construct(0, n, 0, None, options);
+    (state.nodes, state.indices)
+  }
+}
+
+struct Presorted {
+  // 軸ごとに重心順に並べたプリミティブ (各ノードは3つの列で同じ区間を占める)
+  // インデックスを介さずに AABB を順に読めるように、列ごとにプリミティブ自体を持つ
+  orders: [Vec<Primitive>; 3],
+  // 分割で左側に入るプリミティブの印
+  left: Vec<bool>,
+  // 振り分けと SAH の評価に使う作業用の領域 (ノードごとに確保しない)
+  scratch: Vec<Primitive>,
+  areas: Vec<f32>,
+  indices: Vec<usize>,
+  nodes: Vec<Node>,
+}
+
+impl Presorted {
+  // axis は親で選ばれた分割軸で、根では None (葉の中の並びを SweepSah と揃えるため)
+  fn construct(&mut self, begin: usize, end: usize, depth: usize, axis: Option<usize>, options: &BuildOptions) {
+    let n = end - begin;
+    // 要素が1つ以下、または深さの上限に達したときは葉
+    if n <= 1 || depth >= options.max_depth {
+      return self.leaf(begin, end, axis);
+    }
+    // SAHに基づいた最良の分割軸とインデックスを取得
+    let mut best: Option<(usize, (AABB, usize, f32))> = None;
+    for axis in 0..3 {
+      let order = &self.orders[axis][begin..end];
+      let result = sweep_sorted(n, |i| &order[i].aabb, &mut self.areas, options);
+      if best.as_ref().is_none_or( |(_, (_, _, t))| OrderedFloat(result.2) < OrderedFloat(*t) ) {
+        best = Some((axis, result));
+      }
+    }
+    let (partition_axis, (aabb, partition_index, t)) = best.unwrap();
+    // 分割しても全て交差判定するより安くならなければ葉
+    if n <= options.max_leaf_size && n as f32 * options.intersection_cost <= t {
+      return self.leaf(begin, end, Some(partition_axis));
+    }
+    // 他の軸の列を、左右それぞれの中での順序を保ったまま振り分ける
+    let middle = begin + partition_index;
+    for v in &self.orders[partition_axis][begin..end] {
+      self.left[v.index] = false;
+    }
+    for v in &self.orders[partition_axis][begin..middle] {
+      self.left[v.index] = true;
+    }
+    for axis in (0..3).filter( |&axis| axis != partition_axis ) {
+      // 左側はその場で前に詰め、右側だけ作業用の領域を経由する
+      let order = &mut self.orders[axis][begin..end];
+      self.scratch.clear();
+      let mut k = 0;
+      for i in 0..n {
+        if self.left[order[i].index] {
+          order.swap(k, i);
+          k += 1;
+        } else {
+          self.scratch.push(order[i].clone());
+        }
+      }
+      order[k..].clone_from_slice(&self.scratch);
+    }
+    // 深さ優先で配置し、右の子の位置は左の部分木を生成した後に確定
+    let index = self.nodes.len();
+    self.nodes.push(Node {
+      aabb,
+      offset: 0,
+      count: 0,
+    });
+    self.construct(begin, middle, depth + 1, Some(partition_axis), options);
+    self.nodes[index].offset = self.nodes.len();
+    self.construct(middle, end, depth + 1, Some(partition_axis), options);
+  }
+
+  fn leaf(&mut self, begin: usize, end: usize, axis: Option<usize>) {
+    let order = &self.orders[axis.unwrap_or(0)][begin..end];
+    match axis {
+      Some(_) => for (i, v) in self.indices[begin..end].iter_mut().zip(order) {
+        *i = v.index;
+      },
+      // 分割せずに根が葉になるときは並べ替える前の入力の順
+      None => for (k, i) in self.indices[begin..end].iter_mut().enumerate() {
+        *i = begin + k;
+      },
+    }
+    leaf(order, begin, &mut self.nodes);
+  }
+}
+
+// ノードのAABBの最も長い辺の軸について、その中点で分割
+pub struct SpatialMedian;
+
+impl Partition for SpatialMedian {
+  fn partition(&self, list: &mut [Primitive], _options: &BuildOptions) -> (AABB, usize, f32) {
+    let n = list.len();
+    let aabb = list.iter().fold(AABB::empty(), |a, v| a.merge_with(&v.aabb));
+    let axis = longest_axis(&aabb);
+    let middle = (aabb.min[axis] + aabb.max[axis]) / 2.0;
+    // 中点より手前に重心があるものを前方に集める
+    let mut partition_index = 0;
+    for i in 0..n {
+      if list[i].aabb.center[axis] < middle {
+        list.swap(partition_index, i);
+        partition_index += 1;
+      }
+    }
+    // 片側に偏った場合は個数で半分に分割
+    if partition_index == 0 || partition_index == n {
+      sort_axis(list, axis);
+      partition_index = n / 2;
+    }
+    (aabb, partition_index, f32::INFINITY)
+  }
+}
+
+// ノードのAABBの最も長い辺の軸について、重心の順で個数が半分になるように分割
+pub struct ObjectMedian;
+
+impl Partition for ObjectMedian {
+  fn partition(&self, list: &mut [Primitive], _options: &BuildOptions) -> (AABB, usize, f32) {
+    let n = list.len();
+    let aabb = list.iter().fold(AABB::empty(), |a, v| a.merge_with(&v.aabb));
+    let axis = longest_axis(&aabb);
+    // 全体をソートせず n / 2 番目を境に分ける
+    list.select_nth_unstable_by_key(n / 2, |v| (OrderedFloat(v.aabb.center[axis]), v.index));
+    (aabb, n / 2, f32::INFINITY)
+  }
+}
+
+// モートン符号順の前後指定数の範囲で、結合したAABBの表面積が最小のもの同士を下から併合
+pub struct Ploc(pub usize);
+
+impl Builder for Ploc {
+  // Parallel Locally-Ordered Clustering (Meister and Bittner 2018)
+  // モートン符号順に並んだプリミティブ1つずつを葉として併合した後、SAH と max_leaf_size、max_depth に従って部分木を葉にまとめる
+  fn build(&self, aabbs: Vec<AABB>, options: &BuildOptions) -> (Vec<Node>, Vec<usize>) {
+    let radius = self.0;
+    assert!(radius >= 1, "PLOC search radius must be at least 1.");
+    let mut list = primitives(aabbs);
+    sort_morton(&mut list);
+    let n = list.len();
+    // 0..n が葉、それ以降が併合で生まれた節
+    let mut aabbs = list.iter().map( |v| v.aabb.clone() ).collect::<Vec<_>>();
+    let mut children = vec![None; n];
+    let mut clusters = (0..n).collect::<Vec<_>>();
+    while clusters.len() > 1 {
+      let c = clusters.len();
+      // 前後 radius 個の範囲で最も近いクラスタ
+      // 対の全順序で比べることで、少なくとも1組は互いに最も近くなる
+      let nearest = (0..c).map( |i| {
+        (i.saturating_sub(radius)..(i + radius + 1).min(c)).filter( |&j| j != i ).min_by_key( |&j| {
+          let area = aabbs[clusters[i]].merge_with(&aabbs[clusters[j]]).surface_area();
+          (OrderedFloat(area), i.min(j), i.max(j))
+        }).unwrap()
+      }).collect::<Vec<_>>();
+      // 互いに最も近いもの同士を併合し、前の方の位置に置く
+      let mut next = Vec::with_capacity(c);
+      for i in 0..c {
+        let j = nearest[i];
+        if nearest[j] != i {
+          next.push(clusters[i]);
+        } else if i < j {
+          aabbs.push(aabbs[clusters[i]].merge_with(&aabbs[clusters[j]]));
+          children.push(Some((clusters[i], clusters[j])));
+          next.push(aabbs.len() - 1);
+        }
+      }
+      clusters = next;
+    }
+    let root = match clusters.first() {
+      Some(&root) => root,
+      None => {
+        let mut nodes = Vec::with_capacity(1);
+        leaf(&[], 0, &mut nodes);
+        return (nodes, Vec::new());
+      },
+    };
+    // 子は必ず親より前にあるので前から順に、葉にまとめた方が安い部分木を求める
+    // (SAHコストは表面積で重み付けしたもの)
+    let mut count = vec![1; aabbs.len()];
+    let mut cost = vec![0.0; aabbs.len()];
+    let mut collapse = vec![true; aabbs.len()];
+    for i in 0..aabbs.len() {
+      let area = aabbs[i].surface_area();
+      match children[i] {
+        Some((left, right)) => {
+          count[i] = count[left] + count[right];
+          let leaf_cost = area * count[i] as f32 * options.intersection_cost;
+          let node_cost = area * 2.0 * options.traversal_cost + cost[left] + cost[right];
+          collapse[i] = count[i] <= options.max_leaf_size && leaf_cost <= node_cost;
+          cost[i] = if collapse[i] { leaf_cost } else { node_cost };
+        },
+        None => cost[i] = area * options.intersection_cost,
+      }
+    }
+    // 上から辿って、まとめる部分木と深さの上限に達した部分木を葉にする
+    let mut leaves = vec![(0, 0); aabbs.len()];
+    let mut leaf_indices = Vec::with_capacity(n);
+    let mut stack = vec![(root, 0)];
+    while let Some((index, depth)) = stack.pop() {
+      let (left, right) = match children[index] {
+        Some(v) => v,
+        None => {
+          leaves[index] = (leaf_indices.len(), 1);
+          leaf_indices.push(list[index].index);
+          continue;
+        },
+      };
+      if !collapse[index] && depth < options.max_depth {
+        stack.push((right, depth + 1));
+        stack.push((left, depth + 1));
+        continue;
+      }
+      // 部分木のプリミティブを集める
+      let begin = leaf_indices.len();
+      let mut subtree = vec![index];
+      while let Some(i) = subtree.pop() {
+        match children[i] {
+          Some((left, right)) => {
+            subtree.push(right);
+            subtree.push(left);
+          },
+          None => leaf_indices.push(list[i].index),
+        }
+      }
+      leaves[index] = (begin, leaf_indices.len() - begin);
+      children[index] = None;
+    }
+    let mut nodes = Vec::with_capacity(aabbs.len());
+    let mut indices = Vec::with_capacity(n);
+    let primitives = |i: usize| {
+      let (begin, count) = leaves[i];
+      &leaf_indices[begin..begin + count]
+    };
+    flatten(root, &children, &aabbs, &primitives, &mut nodes, &mut indices);
+    (nodes, indices)
+  }
+}
+
+// 物体分割に加え、平面をまたぐ参照を複製して両側に振り分ける空間分割も考慮する
+// Spatial Splits in Bounding Volume Hierarchies (Stich et al. 2009)
+// clip(i, aabb, axis, position) は i 番目のプリミティブのうち aabb の範囲にある部分を
+// axis 軸に垂直な position の平面で切り分けた両側の AABB
+pub struct Sbvh<C> {
+  // 複製してよい参照の数 (プリミティブ数に対する割合)
+  pub budget: f32,
+  pub clip: C,
+}
+
+impl Sbvh<fn(usize, &AABB, usize, f32) -> (AABB, AABB)> {
+  // プリミティブの形が分からないので参照の AABB 自体を切り分ける
+  pub fn new(budget: f32) -> Self {
+    Sbvh {
+      budget,
+      clip: |_, aabb, axis, position| aabb.split(axis, position),
+    }
+  }
+}
+
+impl<C> Builder for Sbvh<C>
+  where
+    C: Fn(usize, &AABB, usize, f32) -> (AABB, AABB),
+{
+  // 参照が分割のたびに増減するので、ノードごとに参照の列を持って1スレッドで構築する
+  fn build(&self, aabbs: Vec<AABB>, options: &BuildOptions) -> (Vec<Node>, Vec<usize>) {
+    assert!(self.budget >= 0.0, "Duplication budget must not be negative.");
+    let list = primitives(aabbs);
+    let n = list.len();
+    let root = list.iter().fold(AABB::empty(), |a, v| a.merge_with(&v.aabb));
+    let mut state = Spatial {
+      clip: &self.clip,
+      limit: (n as f32 * self.budget) as usize,
+      duplicates: 0,
+      root_area: root.surface_area(),
+      nodes: Vec::with_capacity(2 * n),
+      indices: Vec::with_capacity(n),
+    };
+    // 物体分割の軸ごとの評価もスレッドを分けない
+    let serial = BuildOptions {
+      parallel: false,
+      ..*options
+    };
+    state.construct(list, 0, &serial);
+    (state.nodes, state.indices)
+  }
+}
+
+// 空間分割のビンの数
+const SPATIAL_BINS: usize = 32;
+// 物体分割の子同士の重なりが根の表面積に対してこれ以下なら空間分割を試さない
+const SPATIAL_OVERLAP: f32 = 1e-5;
+
+struct Spatial<'a, C> {
+  clip: &'a C,
+  limit: usize,
+  // これまでに複製した参照の数
+  duplicates: usize,
+  root_area: f32,
+  nodes: Vec<Node>,
+  indices: Vec<usize>,
+}
+
+impl<'a, C> Spatial<'a, C>
+  where
+    C: Fn(usize, &AABB, usize, f32) -> (AABB, AABB),
+{
+  fn construct(&mut self, mut list: Vec<Primitive>, depth: usize, options: &BuildOptions) {
+    let n = list.len();
+    // 要素が1つ以下、または深さの上限に達したときは葉
+    if n <= 1 || depth >= options.max_depth {
+      return self.leaf(&list);
+    }
+    // 物体分割 (list は最良の軸で並ぶ)
+    let (aabb, object_index, object_t) = SweepSah.partition(&mut list, options);
+    // 子同士が大きく重なり、複製の余地があるときだけ空間分割を試す
+    let overlap = {
+      let left = list[..object_index].iter().fold(AABB::empty(), |a, v| a.merge_with(&v.aabb));
+      let right = list[object_index..].iter().fold(AABB::empty(), |a, v| a.merge_with(&v.aabb));
+      let overlap = left.overlap_with(&right);
+      if overlap.is_empty() { 0.0 } else { overlap.surface_area() }
+    };
+    let spatial = if overlap > SPATIAL_OVERLAP * self.root_area && self.duplicates < self.limit {
+      self.find_spatial(&list, &aabb, options).filter( |&(_, _, t)| t < object_t )
+    } else {
+      None
+    };
+    let t = spatial.map_or(object_t, |(_, _, t)| t);
+    // 分割しても全て交差判定するより安くならなければ葉
+    if n <= options.max_leaf_size && n as f32 * options.intersection_cost <= t {
+      return self.leaf(&list);
+    }
+    let (left, right) = match spatial.and_then( |(axis, position, _)| self.split_spatial(&list, axis, position) ) {
+      Some(v) => v,
+      None => {
+        let right = list.split_off(object_index);
+        (list, right)
+      },
+    };
+    // 深さ優先で配置し、右の子の位置は左の部分木を生成した後に確定
+    let index = self.nodes.len();
+    self.nodes.push(Node {
+      aabb,
+      offset: 0,
+      count: 0,
+    });
+    self.construct(left, depth + 1, options);
+    self.nodes[index].offset = self.nodes.len();
+    self.construct(right, depth + 1, options);
+  }
+
+  // ビン境界の平面のうちSAHコストが最小のもの (軸、位置、コスト)
+  // 複製が残りの予算を超える平面は選ばない
+  fn find_spatial(&self, list: &[Primitive], aabb: &AABB, options: &BuildOptions) -> Option<(usize, f32, f32)> {
+    let n = list.len();
+    let s_a = aabb.surface_area();
+    let mut best: Option<(usize, f32, f32)> = None;
+    for axis in 0..3 {
+      let min = aabb.min[axis];
+      let extent = aabb.max[axis] - min;
+      if extent <= 0.0 {
+        continue;
+      }
+      let bin = |x: f32| (((x - min) / extent * SPATIAL_BINS as f32) as usize).min(SPATIAL_BINS - 1);
+      let boundary = |b: usize| min + extent * b as f32 / SPATIAL_BINS as f32;
+      // ビンごとの切り分けた AABB と、そのビンで始まる参照と終わる参照の数
+      let mut bin_aabb = vec![AABB::empty(); SPATIAL_BINS];
+      let mut entry = vec![0; SPATIAL_BINS];
+      let mut exit = vec![0; SPATIAL_BINS];
+      for v in list {
+        let first = bin(v.aabb.min[axis]);
+        let last = bin(v.aabb.max[axis]);
+        let mut rest = v.aabb.clone();
+        for (b, aabb) in bin_aabb[first..last].iter_mut().enumerate() {
+          let (left, right) = (self.clip)(v.index, &rest, axis, boundary(first + b + 1));
+          *aabb = aabb.merge_with(&left);
+          rest = right;
+        }
+        bin_aabb[last] = bin_aabb[last].merge_with(&rest);
+        entry[first] += 1;
+        exit[last] += 1;
+      }
+      // S1の表面積と参照数
+      let mut s1_aabb = AABB::empty();
+      let mut s1_n = 0;
+      let mut s1 = Vec::with_capacity(SPATIAL_BINS - 1);
+      for b in 0..SPATIAL_BINS - 1 {
+        s1_aabb = s1_aabb.merge_with(&bin_aabb[b]);
+        s1_n += entry[b];
+        s1.push((s1_aabb.surface_area(), s1_n));
+      }
+      // S2を右から広げながらSAHのスコアを評価
+      let mut s2_aabb = AABB::empty();
+      let mut s2_n = 0;
+      for b in (1..SPATIAL_BINS).rev() {
+        s2_aabb = s2_aabb.merge_with(&bin_aabb[b]);
+        s2_n += exit[b];
+        let (s1_a, s1_n) = s1[b - 1];
+        if s1_n == 0 || s2_n == 0 || self.duplicates + s1_n + s2_n - n > self.limit {
+          continue;
+        }
+        let t = 2.0 * options.traversal_cost +
+          (s1_a * s1_n as f32 + s2_aabb.surface_area() * s2_n as f32) * options.intersection_cost / s_a;
+        if best.is_none_or( |(_, _, best_t)| t < best_t ) {
+          best = Some((axis, boundary(b), t));
+        }
+      }
+    }
+    best
+  }
+
+  // 平面の手前と奥に振り分け、またぐ参照は切り分けて両側に入れる
+  fn split_spatial(&mut self, list: &[Primitive], axis: usize, position: f32) -> Option<(Vec<Primitive>, Vec<Primitive>)> {
+    let mut left = Vec::with_capacity(list.len());
+    let mut right = Vec::with_capacity(list.len());
+    let mut duplicates = 0;
+    for v in list {
+      if v.aabb.max[axis] <= position {
+        left.push(v.clone());
+      } else if v.aabb.min[axis] >= position {
+        right.push(v.clone());
+      } else {
+        let (l, r) = (self.clip)(v.index, &v.aabb, axis, position);
+        // 形状が平面の片側にしか無ければ複製しない
+        if !l.is_empty() && !r.is_empty() {
+          duplicates += 1;
+        }
+        for (side, aabb) in [(&mut left, l), (&mut right, r)] {
+          if !aabb.is_empty() {
+            side.push(Primitive {
+              aabb,
+              ..v.clone()
+            });
+          }
+        }
+      }
+    }
+    // 片側が空になる分割は物体分割に任せる
+    if left.is_empty() || right.is_empty() {
+      return None;
+    }
+    self.duplicates += duplicates;
+    Some((left, right))
+  }
+
+  fn leaf(&mut self, list: &[Primitive]) {
+    self.nodes.push(Node {
+      aabb: list.iter().fold(AABB::empty(), |a, v| a.merge_with(&v.aabb)),
+      offset: self.indices.len(),
+      count: list.len(),
+    });
+    self.indices.extend(list.iter().map( |v| v.index ));
+  }
+}
+
+fn primitives(aabbs: Vec<AABB>) -> Vec<Primitive> {
+  aabbs.into_iter().enumerate().map( |(i, aabb)| Primitive {
+    aabb,
+    index: i,
+    code: 0,
+  }).collect()
+}
+
+fn is_parallel(n: usize, options: &BuildOptions) -> bool {
+  options.parallel && n >= options.parallel_threshold
+}
+
+// 部分木を別スレッドに分ける段数 (スレッド数がコア数の2倍程度に収まるように)
+// 分割が偏ってもコアが遊ばないように log2(コア数) より1段多く分ける (1コアなら分けない)
+fn fork_depth() -> usize {
+  let cores = thread::available_parallelism().map_or(1, |v| v.get());
+  if cores <= 1 {
+    return 0;
+  }
+  cores.next_power_of_two().trailing_zeros() as usize + 1
+}
+
+fn leaf(list: &[Primitive], offset: usize, nodes: &mut Vec<Node>) {
+  nodes.push(Node {
+    aabb: list.iter().fold(AABB::empty(), |a, v| a.merge_with(&v.aabb)),
+    offset,
+    count: list.len(),
+  });
+}
+
+fn longest_axis(aabb: &AABB) -> usize {
+  let side = aabb.side();
+  if side.x >= side.y && side.x >= side.z {
+    0
+  } else if side.y >= side.z {
+    1
+  } else {
+    2
+  }
+}
+
+// 重心が同じものはインデックス順にして、並び替える前の順序によらない結果にする
+fn sort_axis(list: &mut [Primitive], axis: usize) {
+  list.sort_unstable_by_key( |v| {
+    (OrderedFloat(v.aabb.center[axis]), v.index)
+  });
+}
+
+// axis 方向に並べたときの全体のAABBと最良の分割位置、そのSAHコスト
+fn sweep_axis(list: &mut [Primitive], axis: usize, options: &BuildOptions) -> (AABB, usize, f32) {
+  // 基準の軸でソート
+  sort_axis(list, axis);
+  sweep_sorted(list.len(), |i| &list[i].aabb, &mut Vec::with_capacity(list.len()), options)
+}
+
+// 並んだ n 個の AABB (i 番目を aabb(i) で参照) の全体のAABBと最良の分割位置、そのSAHコスト
+// areas は作業用の領域 (呼び出しをまたいで使い回せる)
+fn sweep_sorted<'a, F>(n: usize, aabb: F, areas: &mut Vec<f32>, options: &BuildOptions) -> (AABB, usize, f32)
+  where
+    F: Fn(usize) -> &'a AABB,
+{
+  // S2のAABBの表面積 (areas[n - i - 1] が i 番目以降)
+  areas.clear();
+  let mut s2_aabb = aabb(n - 1).clone();
+  for i in (1..n).rev() {
+    s2_aabb = s2_aabb.merge_with(aabb(i));
+    areas.push(s2_aabb.surface_area());
+  }
+  // 全体SのAABBの表面積
+  let all = s2_aabb.merge_with(aabb(0));
+  let s_a = all.surface_area();
+  // S1を左から広げながらSAHのスコアを評価
+  let mut s1_aabb = aabb(0).clone();
+  let mut best: Option<(usize, f32)> = None;
+  for i in 0..n - 1 {
+    s1_aabb = s1_aabb.merge_with(aabb(i));
+    // ポリゴン数
+    let s1_n = (i + 1) as f32;
+    let s2_n = (n - i - 1) as f32;
+    // Surface Area Heuristics
+    // T = 2 * T_aabb + (A(S1) * N(S1) + A(S2) * N(S2)) * T_tri / A(S)
+    let t = 2.0 * options.traversal_cost +
+      (s1_aabb.surface_area() * s1_n + areas[n - i - 2] * s2_n) * options.intersection_cost / s_a;
+    if best.is_none_or( |(_, best_t)| OrderedFloat(t) < OrderedFloat(best_t) ) {
+      best = Some((i, t));
+    }
+  }
+  let (i, t) = best.unwrap();
+  (all, i + 1, t)
+}
+
+// 重心の範囲を各軸 10bit に量子化したモートン符号で並べ替える
+fn sort_morton(list: &mut [Primitive]) {
+  let centroid = list.iter().fold(AABB::empty(), |a, v| a.merge_with(&AABB {
+    min: v.aabb.center,
+    max: v.aabb.center,
+    center: v.aabb.center,
+  }));
+  // 下位から2bitおきに間を空ける
+  let expand = |v: u32| {
+    let v = (v | (v << 16)) & 0x030000ff;
+    let v = (v | (v << 8)) & 0x0300f00f;
+    let v = (v | (v << 4)) & 0x030c30c3;
+    (v | (v << 2)) & 0x09249249
+  };
+  for v in list.iter_mut() {
+    let q = (0..3).map( |axis| {
+      let extent = centroid.max[axis] - centroid.min[axis];
+      if extent > 0.0 {
+        (((v.aabb.center[axis] - centroid.min[axis]) / extent * 1024.0) as u32).min(1023)
+      } else {
+        0
+      }
+    }).collect::<Vec<_>>();
+    v.code = (expand(q[0]) << 2) | (expand(q[1]) << 1) | expand(q[2]);
+  }
+  list.sort_unstable_by_key( |v| v.code );
+}
+
+// 子の参照で表した木を深さ優先の並びにする (leaf は葉に含まれるプリミティブ)
+pub(crate) fn flatten<'a, L>(
+  index: usize,
+  children: &[Option<(usize, usize)>],
+  aabbs: &[AABB],
+  leaf: &L,
+  nodes: &mut Vec<Node>,
+  indices: &mut Vec<usize>,
+)
+  where
+    L: Fn(usize) -> &'a [usize],
+{
+  match children[index] {
+    Some((left, right)) => {
+      let i = nodes.len();
+      nodes.push(Node {
+        aabb: aabbs[index].clone(),
+        offset: 0,
+        count: 0,
+      });
+      flatten(left, children, aabbs, leaf, nodes, indices);
+      nodes[i].offset = nodes.len();
+      flatten(right, children, aabbs, leaf, nodes, indices);
+    },
+    None => {
+      let list = leaf(index);
+      nodes.push(Node {
+        aabb: aabbs[index].clone(),
+        offset: indices.len(),
+        count: list.len(),
+      });
+      indices.extend_from_slice(list);
+    },
+  }
+}
+
+// 一方の子ともう一方の子の子を入れ替えたときに表面積が最も減るもの
+// (a, b, swap, keep, aabb): 子 a と b の子 swap を入れ替え、b の子は (a, keep)、AABB は aabb になる
+pub(crate) fn best_rotation<'a, C, A>(left: usize, right: usize, children: C, aabb: A) -> Option<(usize, usize, usize, usize, AABB)>
+  where
+    C: Fn(usize) -> Option<(usize, usize)>,
+    A: Fn(usize) -> &'a AABB,
+{
+  let mut best = None;
+  let mut best_gain = 0.0;
+  for &(a, b) in [(left, right), (right, left)].iter() {
+    if let Some((b0, b1)) = children(b) {
+      for &(swap, keep) in [(b0, b1), (b1, b0)].iter() {
+        let merged = aabb(a).merge_with(aabb(keep));
+        let gain = aabb(b).surface_area() - merged.surface_area();
+        if gain > best_gain {
+          best_gain = gain;
+          best = Some((a, b, swap, keep, merged));
+        }
+      }
+    }
+  }
+  best
+}
@@ -1,146 +1,386 @@
-extern crate ordered_float;
-
-use aabb::AABB;
-use shape::*;
-use ray::Ray;
-use intersection::Intersection;
-use self::ordered_float::OrderedFloat;
-
-#[derive(Clone)]
-struct Leaf {
-  aabb: AABB,
-  index: usize,
-}
+use crate::aabb::AABB;
+use crate::shape::*;
+use crate::ray::Ray;
+use crate::intersection::Intersection;
+use crate::builder::*;
 
-trait Branch {
-  fn may_intersect(&self, &Ray, &mut Vec<usize>);
-  fn aabb(&self) -> &AABB;
+// 分割位置の探索方法
+#[derive(Clone, Copy)]
+pub enum Split {
+  // 全ての分割位置でSAHを評価
+  Sweep,
+  // Sweep と同じ木を、各軸で一度だけソートした列を振り分けながら構築
+  PresortedSweep,
+  // 重心を指定数のビンに分け、ビン境界でのみSAHを評価
+  Binned(usize),
+  // 重心のモートン符号で一度だけソートし、符号の最上位の異なるビットで分割 (LBVH)
+  Morton,
+  // モートン符号順の前後指定数の範囲で、結合したAABBの表面積が最小のもの同士を下から併合 (PLOC)
+  Ploc(usize),
+  // SweepSah の物体分割に加え、平面をまたぐ参照を複製する空間分割も評価 (SBVH)
+  // 複製する参照はプリミティブ数の budget 倍まで
+  Sbvh { budget: f32 },
+  // 最も長い辺の中点で分割 (比較用)
+  SpatialMedian,
+  // 重心の順で個数が半分になるように分割 (比較用)
+  ObjectMedian,
 }
 
-impl Branch for Leaf {
-  fn may_intersect(&self, ray: &Ray, candidate: &mut Vec<usize>) {
-    if self.aabb.is_intersect(ray) {
-      candidate.push(self.index)
+impl Split {
+  // 対応する構築方法
+  pub fn builder(self) -> Box<dyn Builder> {
+    match self {
+      Split::Sweep => Box::new(TopDown(SweepSah)),
+      Split::PresortedSweep => Box::new(PresortedSah),
+      Split::Binned(bins) => Box::new(TopDown(BinnedSah(bins))),
+      Split::Morton => Box::new(TopDown(Morton)),
+      Split::Ploc(radius) => Box::new(Ploc(radius)),
+      // 形状が分からないので AABB 自体を切り分ける (BVH や TriangleMesh では形状に沿って切る)
+      Split::Sbvh { budget } => Box::new(Sbvh::new(budget)),
+      Split::SpatialMedian => Box::new(TopDown(SpatialMedian)),
+      Split::ObjectMedian => Box::new(TopDown(ObjectMedian)),
     }
   }
+}
 
-  fn aabb(&self) -> &AABB {
-    &self.aabb
+// 構築時のパラメータ
+#[derive(Clone, Copy)]
+pub struct BuildOptions {
+  pub split: Split,
+  // AABBとの交差判定コスト
+  pub traversal_cost: f32,
+  // プリミティブとの交差判定コスト
+  pub intersection_cost: f32,
+  // これを超える数のプリミティブは必ず分割する
+  pub max_leaf_size: usize,
+  // この深さに達したら残りを全て葉にする
+  pub max_depth: usize,
+  // false なら常に1スレッドで構築 (並列でも同じ木になる)
+  pub parallel: bool,
+  // これ以上のプリミティブを持つノードでは部分木と軸ごとの評価を別スレッドで行う
+  // (スレッドを分けるのはコア数に応じた上の数段のみ)
+  pub parallel_threshold: usize,
+}
+
+impl Default for BuildOptions {
+  fn default() -> BuildOptions {
+    BuildOptions {
+      split: Split::Sweep,
+      traversal_cost: 1.0,
+      intersection_cost: 2.0,
+      max_leaf_size: 4,
+      max_depth: 64,
+      parallel: true,
+      parallel_threshold: 4096,
+    }
   }
 }
 
-struct Node {
-  aabb: AABB,
-  left: Box<Branch>,
-  right: Box<Branch>,
+// プリミティブ自体は持たず、AABBの並びに対してのみ構築される階層
+pub struct Hierarchy {
+  nodes: Vec<Node>,
+  // 空間分割で複製された参照を含む
+  indices: Vec<usize>,
+  primitive_count: usize,
+  options: BuildOptions,
 }
 
-impl Branch for Node {
-  fn may_intersect(&self, ray: &Ray, mut candidate: &mut Vec<usize>) {
-    if self.aabb.is_intersect(ray) {
-      self.left.may_intersect(ray, &mut candidate);
-      self.right.may_intersect(ray, &mut candidate);
+impl Hierarchy {
+  pub fn new<I>(aabbs: I, options: BuildOptions) -> Hierarchy
+    where
+      I: IntoIterator<Item = AABB>,
+  {
+    Self::with_builder(aabbs, &*options.split.builder(), options)
+  }
+
+  // options.split の代わりに builder で構築する
+  pub fn with_builder<I, B>(aabbs: I, builder: &B, options: BuildOptions) -> Hierarchy
+    where
+      I: IntoIterator<Item = AABB>,
+      B: Builder + ?Sized,
+  {
+    let aabbs = aabbs.into_iter().collect::<Vec<_>>();
+    let primitive_count = aabbs.len();
+    let (nodes, indices) = builder.build(aabbs, &options);
+    // 外部の Builder も探索が前提とする並びを守っているか確かめる
+    assert!(!nodes.is_empty(), "Builder must return a root node.");
+    assert!(indices.iter().all( |&i| i < primitive_count ), "Builder must only refer to given primitives.");
+    assert_eq!(
+      Self::subtree_end(&nodes, indices.len(), 0), nodes.len(),
+      "Builder must return exactly the nodes reachable from the root.",
+    );
+    Hierarchy {
+      nodes,
+      indices,
+      primitive_count,
+      options,
     }
   }
 
-  fn aabb(&self) -> &AABB {
-    &self.aabb
+  // 深さ優先に並んだ部分木の直後のインデックス
+  fn subtree_end(nodes: &[Node], index_count: usize, index: usize) -> usize {
+    let node = &nodes[index];
+    // 中身の無い葉は空の階層の根としてのみ許す
+    if node.count > 0 || (nodes.len() == 1 && index_count == 0) {
+      assert!(node.offset + node.count <= index_count, "Leaf must refer to a range within the indices.");
+      return index + 1;
+    }
+    assert!(index + 1 < nodes.len() && node.offset < nodes.len(), "Child must be within the nodes.");
+    // 左の子は直後に、右の子は左の部分木の直後に並ぶ
+    assert_eq!(Self::subtree_end(nodes, index_count, index + 1), node.offset, "Right child must follow the left subtree.");
+    Self::subtree_end(nodes, index_count, node.offset)
+  }
+
+  pub fn node_count(&self) -> usize {
+    self.nodes.len()
+  }
+
+  // 空間分割で複製された参照の数 (SBVH 以外では0)
+  pub fn duplicates(&self) -> usize {
+    self.indices.len() - self.primitive_count
+  }
+
+  // 木全体のSAHコスト
+  pub fn cost(&self) -> f32 {
+    // 空の階層の根は子を持たない葉なので数えない
+    if self.indices.is_empty() {
+      return 0.0;
+    }
+    let s_a = self.nodes[0].aabb.surface_area();
+    self.nodes.iter().map( |node| {
+      let t = if node.count > 0 {
+        node.count as f32 * self.options.intersection_cost
+      } else {
+        2.0 * self.options.traversal_cost
+      };
+      node.aabb.surface_area() / s_a * t
+    }).sum()
+  }
+
+  pub fn aabb(&self) -> &AABB {
+    &self.nodes[0].aabb
+  }
+
+  // 木の回転 (Kopta et al. 2012) を繰り返してSAHコストを下げ、前後のコストを返す
+  pub fn optimize(&mut self) -> (f32, f32) {
+    let before = self.cost();
+    if self.indices.is_empty() {
+      return (before, before);
+    }
+    // 回転しやすいように子の参照で表した木に直す
+    let mut children = self.nodes.iter().enumerate().map( |(i, node)| {
+      if node.count > 0 { None } else { Some((i + 1, node.offset)) }
+    }).collect::<Vec<_>>();
+    let mut aabbs = self.nodes.iter().map( |v| v.aabb.clone() ).collect::<Vec<_>>();
+    for _ in 0..16 {
+      if !Self::rotate(0, &mut children, &mut aabbs) {
+        break;
+      }
+    }
+    // 深さ優先の並びに戻す
+    let mut nodes = Vec::with_capacity(self.nodes.len());
+    let mut indices = Vec::with_capacity(self.indices.len());
+    let leaf = |i: usize| {
+      let node = &self.nodes[i];
+      &self.indices[node.offset..node.offset + node.count]
+    };
+    flatten(0, &children, &aabbs, &leaf, &mut nodes, &mut indices);
+    self.nodes = nodes;
+    self.indices = indices;
+    (before, self.cost())
+  }
+
+  // 部分木を下から順に回転し、1度でも回転したかを返す
+  fn rotate(index: usize, children: &mut [Option<(usize, usize)>], aabbs: &mut [AABB]) -> bool {
+    let (left, right) = match children[index] {
+      Some(v) => v,
+      None => return false,
+    };
+    let rotated_left = Self::rotate(left, children, aabbs);
+    let rotated_right = Self::rotate(right, children, aabbs);
+    let best = best_rotation(left, right, |i| children[i], |i| &aabbs[i]);
+    match best {
+      Some((a, b, swap, keep, aabb)) => {
+        children[index] = Some(if a == left { (swap, b) } else { (b, swap) });
+        children[b] = Some((a, keep));
+        aabbs[b] = aabb;
+        true
+      },
+      None => rotated_left || rotated_right,
+    }
+  }
+
+  // 木の構造を保ったまま、各ノードの AABB をプリミティブの現在の AABB から下から順に計算し直す
+  // (aabbs は構築時と同じ並びで与える。複製された参照は切り分ける前の AABB で包む)
+  pub fn refit(&mut self, aabbs: &[AABB]) {
+    assert_eq!(aabbs.len(), self.primitive_count, "Primitive count must not change.");
+    // 子は必ず親より後ろに並んでいるので逆順に辿ればよい
+    for index in (0..self.nodes.len()).rev() {
+      let node = &self.nodes[index];
+      let aabb = if node.count > 0 {
+        self.indices[node.offset..node.offset + node.count].iter().fold(AABB::empty(), |a, &i| {
+          a.merge_with(&aabbs[i])
+        })
+      } else {
+        self.nodes[index + 1].aabb.merge_with(&self.nodes[node.offset].aabb)
+      };
+      self.nodes[index].aabb = aabb;
+    }
+  }
+
+  // i 番目のプリミティブとの交差判定を f に委ねて最近交差を求める
+  pub fn intersect<F>(&self, ray: &Ray, f: F) -> Option<Intersection>
+    where
+      F: Fn(usize, &Ray) -> Option<Intersection>,
+  {
+    // プリミティブが無いときの根は中身の無い葉で、AABB も空になっている
+    if self.indices.is_empty() {
+      return None;
+    }
+    let mut closest = None;
+    if self.nodes[0].aabb.is_intersect(ray) {
+      self.intersect_node(0, ray, &f, &mut closest);
+    }
+    closest
+  }
+
+  pub fn occluded<F>(&self, ray: &Ray, t_max: f32, f: F) -> bool
+    where
+      F: Fn(usize, &Ray, f32) -> bool,
+  {
+    !self.indices.is_empty() && self.occluded_node(0, ray, t_max, &f)
+  }
+
+  fn intersect_node<F>(&self, index: usize, ray: &Ray, f: &F, closest: &mut Option<Intersection>)
+    where
+      F: Fn(usize, &Ray) -> Option<Intersection>,
+  {
+    let node = &self.nodes[index];
+    if node.count > 0 {
+      for &i in &self.indices[node.offset..node.offset + node.count] {
+        // 既知の最近交差より遠い交差は探さない
+        let t_max = closest.as_ref().map_or(ray.t_max, |c| c.distance);
+        let ray = &Ray::with_interval(ray.origin, ray.direction, ray.t_min, t_max);
+        if let Some(v) = f(i, ray) {
+          if closest.as_ref().is_none_or(|c| v.distance < c.distance) {
+            *closest = Some(v);
+          }
+        }
+      }
+      return;
+    }
+    let left = index + 1;
+    let right = node.offset;
+    let left_t = self.nodes[left].aabb.intersect_distance(ray);
+    let right_t = self.nodes[right].aabb.intersect_distance(ray);
+    // 進入距離の近い子から順に辿る
+    let order = match (left_t, right_t) {
+      (Some(l), Some(r)) if r < l => [(right_t, right), (left_t, left)],
+      _ => [(left_t, left), (right_t, right)],
+    };
+    for &(t, child) in order.iter() {
+      if let Some(t) = t {
+        // 既知の最近交差より遠い子は枝刈り
+        if closest.as_ref().is_none_or(|c| t <= c.distance) {
+          self.intersect_node(child, ray, f, closest);
+        }
+      }
+    }
+  }
+
+  fn occluded_node<F>(&self, index: usize, ray: &Ray, t_max: f32, f: &F) -> bool
+    where
+      F: Fn(usize, &Ray, f32) -> bool,
+  {
+    let node = &self.nodes[index];
+    match node.aabb.intersect_distance(ray) {
+      Some(t) if t < t_max => (),
+      _ => return false,
+    }
+    if node.count > 0 {
+      return self.indices[node.offset..node.offset + node.count].iter().any( |&i| {
+        f(i, ray, t_max)
+      });
+    }
+    self.occluded_node(index + 1, ray, t_max, f) || self.occluded_node(node.offset, ray, t_max, f)
   }
 }
 
 pub struct BVH<'a> {
-  list: &'a [Box<Shape>],
-  root: Box<Branch>,
+  list: &'a [Box<dyn Shape>],
+  hierarchy: Hierarchy,
 }
 
 impl<'a> BVH<'a> {
-  pub fn new(list: &'a [Box<Shape>]) -> BVH<'a> {
-    let mut leaf = list.iter().enumerate().map( |(i, v)| Leaf {
-      aabb: v.aabb().clone(),
-      index: i,
-    }).collect::<Vec<_>>();
-    let root = Self::construct(&mut leaf);
+  pub fn new(list: &'a [Box<dyn Shape>]) -> BVH<'a> {
+    Self::with_options(list, BuildOptions::default())
+  }
+
+  pub fn with_options(list: &'a [Box<dyn Shape>], options: BuildOptions) -> BVH<'a> {
+    match options.split {
+      // 空間分割では各形状に沿って参照を切り分ける
+      Split::Sbvh { budget } => {
+        let clip = |i: usize, aabb: &AABB, axis, position| list[i].split_aabb(aabb, axis, position);
+        Self::with_builder(list, &Sbvh { budget, clip }, options)
+      },
+      _ => Self::with_builder(list, &*options.split.builder(), options),
+    }
+  }
+
+  pub fn with_builder<B>(list: &'a [Box<dyn Shape>], builder: &B, options: BuildOptions) -> BVH<'a>
+    where
+      B: Builder + ?Sized,
+  {
     BVH {
-      list: list,
-      root: root, 
-    }
-  }
-
-  fn construct(list: &mut [Leaf]) -> Box<Branch> {
-    // TODO
-    let t_aabb = 1.0;
-    let t_tri = 2.0;
-    // セットアップ
-    let n = list.len();
-    // 要素が1つのときは葉
-    if n == 1 {
-      return box list[0].clone();
-    }
-    // 全体のAABB
-    let mut aabb = AABB::empty();
-    // SAHに基づいた最良の分割軸とインデックスを取得
-    let (partition_axis, partition_index, _) = (0..3).map( |axis| {
-      // 基準の軸でソート
-      list.sort_unstable_by_key( |v| {
-        OrderedFloat(v.aabb.center[axis])
-      });
-      // S1のAABBの表面積
-      let mut s1_aabb = list[0].aabb.clone();
-      let mut s1_a = Vec::with_capacity(n - 1);
-      for leaf in list[0..n].iter() {
-        s1_aabb = s1_aabb.merge_with(&leaf.aabb);
-        s1_a.push(s1_aabb.surface_area());
-      }
-      // S2のAABBの表面積
-      let mut s2_aabb = list[n - 1].aabb.clone();
-      let mut s2_a = Vec::with_capacity(n - 1);
-      for leaf in list[1..].iter().rev() {
-        s2_aabb = s2_aabb.merge_with(&leaf.aabb);
-        s2_a.push(s2_aabb.surface_area());
-      }
-      // 全体SのAABBの表面積
-      aabb = s1_aabb.merge_with(&list[n - 1].aabb);
-      let s_a = aabb.surface_area();
-      // SAHのスコアを評価
-      (0..n - 1).map( |i| {
-        // ポリゴン数
-        let s1_n = (i + 1) as f32;
-        let s2_n = (n - i - 1) as f32;
-        // Surface Area Heuristics
-        // T = 2 * T_aabb + (A(S1) * N(S1) + A(S2) * N(S2)) * T_tri / A(S)
-        OrderedFloat(2.0 * t_aabb + (s1_a[i] * s1_n + s2_a[n - i - 2] * s2_n) * t_tri / s_a)
-      }).enumerate().min_by_key( |&(_, t)| t ).unwrap()
-    }).enumerate().min_by_key( |&(_, (_, t))| t ).map( |(a, (i, t))| (a, i + 1, t) ).unwrap();
-    // 基準の軸でソート
-    list.sort_unstable_by_key( |v| {
-      OrderedFloat(v.aabb.center[partition_axis])
-    });
-    // 再帰的に子要素を生成
-    debug_assert!(partition_index != 0 && partition_index != n);
-    let left = Self::construct(&mut list[0..partition_index]);
-    let right = Self::construct(&mut list[partition_index..]);
-    box Node {
-      aabb: aabb,
-      left: left,
-      right: right,
+      list,
+      hierarchy: Hierarchy::with_builder(list.iter().map( |v| v.aabb().clone() ), builder, options),
     }
   }
+
+  pub fn hierarchy(&self) -> &Hierarchy {
+    &self.hierarchy
+  }
+
+  // 同じ数の (動いた) 形状に差し替えて、木の構造を保ったまま AABB だけ更新する
+  pub fn refit(&mut self, list: &'a [Box<dyn Shape>]) {
+    self.list = list;
+    let aabbs = list.iter().map( |v| v.aabb().clone() ).collect::<Vec<_>>();
+    self.hierarchy.refit(&aabbs);
+  }
+
+  // 木の回転によってSAHコストを下げ、前後のコストを返す
+  pub fn optimize(&mut self) -> (f32, f32) {
+    self.hierarchy.optimize()
+  }
+
+  pub fn node_count(&self) -> usize {
+    self.hierarchy.node_count()
+  }
+
+  pub fn cost(&self) -> f32 {
+    self.hierarchy.cost()
+  }
+
+  pub fn duplicates(&self) -> usize {
+    self.hierarchy.duplicates()
+  }
 }
 
 impl<'a> Shape for BVH<'a> {
   fn intersect(&self, ray: &Ray) -> Option<Intersection> {
-    let mut candidate = Vec::new();
-    self.root.may_intersect(ray, &mut candidate);
-    candidate.iter().flat_map( |&i| {
-      self.list[i].intersect(&ray)
-    }).min_by(
-      |a, b| {
-        a.distance.partial_cmp(&b.distance).unwrap()
-      },
-    )
+    self.hierarchy.intersect(ray, |i, ray| {
+      self.list[i].intersect(ray).map( |mut v| {
+        v.index = i;
+        v
+      })
+    })
+  }
+
+  fn occluded(&self, ray: &Ray, t_max: f32) -> bool {
+    self.hierarchy.occluded(ray, t_max, |i, ray, t_max| self.list[i].occluded(ray, t_max))
   }
 
   fn aabb(&self) -> &AABB {
-    self.root.aabb()
+    self.hierarchy.aabb()
   }
 }
@@ -0,0 +1,83 @@
+use crate::intersection::Intersection;
+use crate::shape::*;
+use crate::constant::*;
+use crate::ray::Ray;
+use crate::math::vector::*;
+use crate::aabb::AABB;
+use crate::sphere::Sphere;
+use crate::cylinder::Cylinder;
+
+// p0 から p1 までの線分から radius 以内の領域
+pub struct Capsule {
+  pub p0: Vector3,
+  pub p1: Vector3,
+  pub radius: f32,
+  aabb: AABB,
+}
+
+impl Capsule {
+  pub fn new(p0: Vector3, p1: Vector3, radius: f32) -> Capsule {
+    let r = Vector3::new(radius, radius, radius);
+    let min = Vector3::new(p0.x.min(p1.x), p0.y.min(p1.y), p0.z.min(p1.z)) - r;
+    let max = Vector3::new(p0.x.max(p1.x), p0.y.max(p1.y), p0.z.max(p1.z)) + r;
+    Capsule {
+      p0,
+      p1,
+      radius,
+      aabb: AABB {
+        min,
+        max,
+        center: (min + max) / 2.0,
+      },
+    }
+  }
+}
+
+impl Shape for Capsule {
+  fn aabb(&self) -> &AABB {
+    &self.aabb
+  }
+
+  fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+    let h = (self.p1 - self.p0).norm();
+    // 線分が潰れている場合は球として扱う
+    let axis = if h < EPS {
+      Vector3::new(0.0, 0.0, 1.0)
+    } else {
+      (self.p1 - self.p0) / h
+    };
+    let y = |t: f32| (ray.origin + ray.direction * t - self.p0).dot(axis);
+    // 受け持ち範囲 (軸方向の位置) にある近い方の交差
+    let nearest = |roots: Option<(f32, f32)>, covers: &dyn Fn(f32) -> bool| {
+      roots.and_then( |(t0, t1)| {
+        [t0, t1].iter().cloned().find( |&t| t >= ray.t_min && t <= ray.t_max && covers(y(t)) )
+      })
+    };
+    // 側面と両端の半球の交差のうち最も近いもの
+    let t = [
+      nearest(Cylinder::roots(self.p0, axis, self.radius, ray), &|y| (0.0..=h).contains(&y)),
+      nearest(Sphere::roots(self.p0, self.radius, ray), &|y| y <= 0.0),
+      nearest(Sphere::roots(self.p1, self.radius, ray), &|y| y >= h),
+    ].iter().flatten().cloned().fold(f32::INFINITY, f32::min);
+    if t == f32::INFINITY {
+      return None;
+    }
+    let position = ray.origin + ray.direction * t;
+    let y = y(t);
+    let r = position - (self.p0 + axis * y.clamp(0.0, h));
+    let normal = r / self.radius;
+    let u = Cylinder::angle(axis, r);
+    let v = (y + self.radius) / (h + 2.0 * self.radius);
+    Some(Intersection {
+      distance: t,
+      normal,
+      position,
+      index: 0,
+      instance: 0,
+      u,
+      v,
+      shading_normal: normal,
+      texcoord: (u, v),
+    })
+  }
+}
@@ -1,3 +1,2 @@
-pub const PI: f32 = 3.14159265358979323846264338327950288_f32;
+pub const PI: f32 = std::f32::consts::PI;
 pub const EPS: f32 = 1e-5;
-pub const INF: f32 = 1e5;
@@ -0,0 +1,100 @@
+use crate::intersection::Intersection;
+use crate::shape::*;
+use crate::constant::*;
+use crate::ray::Ray;
+use crate::math::vector::*;
+use crate::aabb::AABB;
+
+// p0 から p1 までの有限円柱の側面 (蓋が必要なら Disk を併用する)
+pub struct Cylinder {
+  pub p0: Vector3,
+  pub p1: Vector3,
+  pub radius: f32,
+  aabb: AABB,
+}
+
+impl Cylinder {
+  pub fn new(p0: Vector3, p1: Vector3, radius: f32) -> Cylinder {
+    let axis = (p1 - p0).normalize();
+    // 両端の円の各軸方向への広がり
+    let extent = Vector3::new(
+      radius * (1.0 - axis.x * axis.x).max(0.0).sqrt(),
+      radius * (1.0 - axis.y * axis.y).max(0.0).sqrt(),
+      radius * (1.0 - axis.z * axis.z).max(0.0).sqrt(),
+    );
+    let min = Vector3::new(p0.x.min(p1.x), p0.y.min(p1.y), p0.z.min(p1.z)) - extent;
+    let max = Vector3::new(p0.x.max(p1.x), p0.y.max(p1.y), p0.z.max(p1.z)) + extent;
+    Cylinder {
+      p0,
+      p1,
+      radius,
+      aabb: AABB {
+        min,
+        max,
+        center: (min + max) / 2.0,
+      },
+    }
+  }
+
+  // p0 を通り axis (単位ベクトル) 方向に無限に伸びる円柱との2つの交差距離 (小さい順)
+  pub(crate) fn roots(p0: Vector3, axis: Vector3, radius: f32, ray: &Ray) -> Option<(f32, f32)> {
+    // 軸に垂直な平面へ射影して円との交差に帰着
+    let o = ray.origin - p0;
+    let o = o - axis * o.dot(axis);
+    let d = ray.direction - axis * ray.direction.dot(axis);
+    let a = d.dot(d);
+    // 軸に平行なレイは側面と交差しない
+    if a < EPS * EPS {
+      return None;
+    }
+    let b = o.dot(d);
+    let c = o.dot(o) - radius * radius;
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+      return None;
+    }
+    let q = -(b + b.signum() * discriminant.sqrt());
+    let (t0, t1) = (q / a, c / q);
+    Some(if t0 < t1 { (t0, t1) } else { (t1, t0) })
+  }
+
+  // 軸まわりの角度を [0, 1] で表したもの
+  pub(crate) fn angle(axis: Vector3, r: Vector3) -> f32 {
+    let (s, b) = axis.orthonormal_basis();
+    (r.dot(b).atan2(r.dot(s)) + PI) / (2.0 * PI)
+  }
+}
+
+impl Shape for Cylinder {
+  fn aabb(&self) -> &AABB {
+    &self.aabb
+  }
+
+  fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+    let h = (self.p1 - self.p0).norm();
+    let axis = (self.p1 - self.p0) / h;
+    let (t0, t1) = Self::roots(self.p0, axis, self.radius, ray)?;
+    // 範囲内かつ両端の間にある近い方の交差
+    let (t, y) = [t0, t1].iter().map( |&t| {
+      (t, (ray.origin + ray.direction * t - self.p0).dot(axis))
+    }).find( |&(t, y)| {
+      t >= ray.t_min && t <= ray.t_max && (0.0..=h).contains(&y)
+    })?;
+    let position = ray.origin + ray.direction * t;
+    let r = position - (self.p0 + axis * y);
+    let normal = r / self.radius;
+    let u = Self::angle(axis, r);
+    let v = y / h;
+    Some(Intersection {
+      distance: t,
+      normal,
+      position,
+      index: 0,
+      instance: 0,
+      u,
+      v,
+      shading_normal: normal,
+      texcoord: (u, v),
+    })
+  }
+}
@@ -0,0 +1,73 @@
+use crate::intersection::Intersection;
+use crate::shape::*;
+use crate::constant::*;
+use crate::ray::Ray;
+use crate::math::vector::*;
+use crate::aabb::AABB;
+
+pub struct Disk {
+  pub center: Vector3,
+  pub normal: Vector3,
+  pub radius: f32,
+  aabb: AABB,
+}
+
+impl Disk {
+  pub fn new(center: Vector3, normal: Vector3, radius: f32) -> Disk {
+    let normal = normal.normalize();
+    // 法線方向に垂直な円の各軸方向への広がり
+    let extent = Vector3::new(
+      radius * (1.0 - normal.x * normal.x).max(0.0).sqrt(),
+      radius * (1.0 - normal.y * normal.y).max(0.0).sqrt(),
+      radius * (1.0 - normal.z * normal.z).max(0.0).sqrt(),
+    );
+    Disk {
+      center,
+      normal,
+      radius,
+      aabb: AABB {
+        min: center - extent,
+        max: center + extent,
+        center,
+      },
+    }
+  }
+}
+
+impl Shape for Disk {
+  fn aabb(&self) -> &AABB {
+    &self.aabb
+  }
+
+  fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+    let denom = self.normal.dot(ray.direction);
+    if denom.abs() < EPS {
+      return None;
+    }
+    let t = self.normal.dot(self.center - ray.origin) / denom;
+    if t < ray.t_min || t > ray.t_max {
+      return None;
+    }
+    let position = ray.origin + ray.direction * t;
+    let r = position - self.center;
+    let distance = r.norm();
+    if distance > self.radius {
+      return None;
+    }
+    // 極座標によるパラメータ化
+    let (s, b) = self.normal.orthonormal_basis();
+    let u = (r.dot(b).atan2(r.dot(s)) + PI) / (2.0 * PI);
+    let v = distance / self.radius;
+    Some(Intersection {
+      distance: t,
+      normal: self.normal,
+      position,
+      index: 0,
+      instance: 0,
+      u,
+      v,
+      shading_normal: self.normal,
+      texcoord: (u, v),
+    })
+  }
+}
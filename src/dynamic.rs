@@ -0,0 +1,400 @@
+use crate::aabb::AABB;
+use crate::shape::*;
+use crate::ray::Ray;
+use crate::intersection::Intersection;
+use crate::bvh::BuildOptions;
+use crate::builder::best_rotation;
+use ordered_float::OrderedFloat;
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+// 追加した形状を指すハンドル
+// 削除後に同じ位置が再利用されても、世代が異なるので古いハンドルは無効のまま
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Handle {
+  index: usize,
+  generation: u32,
+}
+
+impl Handle {
+  // 交差判定の Intersection::index と対応する位置
+  pub fn index(&self) -> usize {
+    self.index
+  }
+}
+
+struct Slot {
+  shape: Option<Box<dyn Shape>>,
+  generation: u32,
+  // 形状を持つ葉のノード
+  leaf: usize,
+}
+
+struct Node {
+  aabb: AABB,
+  parent: Option<usize>,
+  // 節なら2つの子、葉なら None
+  children: Option<(usize, usize)>,
+  // 葉が持つ形状の位置
+  slot: usize,
+}
+
+// 形状の追加と削除ができる階層 (葉は形状1つ)
+pub struct DynamicBVH {
+  nodes: Vec<Node>,
+  free_nodes: Vec<usize>,
+  root: Option<usize>,
+  slots: Vec<Slot>,
+  free_slots: Vec<usize>,
+  options: BuildOptions,
+  empty: AABB,
+}
+
+impl DynamicBVH {
+  pub fn new() -> DynamicBVH {
+    Self::with_options(BuildOptions::default())
+  }
+
+  // options はコストの評価にのみ使う
+  pub fn with_options(options: BuildOptions) -> DynamicBVH {
+    DynamicBVH {
+      nodes: Vec::new(),
+      free_nodes: Vec::new(),
+      root: None,
+      slots: Vec::new(),
+      free_slots: Vec::new(),
+      options,
+      empty: AABB::empty(),
+    }
+  }
+
+  pub fn insert(&mut self, shape: Box<dyn Shape>) -> Handle {
+    let aabb = shape.aabb().clone();
+    let slot = match self.free_slots.pop() {
+      Some(slot) => slot,
+      None => {
+        self.slots.push(Slot {
+          shape: None,
+          generation: 0,
+          leaf: 0,
+        });
+        self.slots.len() - 1
+      },
+    };
+    let leaf = self.allocate(Node {
+      aabb,
+      parent: None,
+      children: None,
+      slot,
+    });
+    self.slots[slot].shape = Some(shape);
+    self.slots[slot].leaf = leaf;
+    let handle = Handle {
+      index: slot,
+      generation: self.slots[slot].generation,
+    };
+    let root = match self.root {
+      Some(root) => root,
+      None => {
+        self.root = Some(leaf);
+        return handle;
+      },
+    };
+    // 兄弟にすると木全体の表面積の増加が最小になるノードを探す
+    let sibling = self.find_sibling(root, &self.nodes[leaf].aabb);
+    let parent = self.nodes[sibling].parent;
+    let node = self.allocate(Node {
+      aabb: self.nodes[sibling].aabb.merge_with(&self.nodes[leaf].aabb),
+      parent,
+      children: Some((sibling, leaf)),
+      slot: 0,
+    });
+    self.nodes[sibling].parent = Some(node);
+    self.nodes[leaf].parent = Some(node);
+    match parent {
+      Some(parent) => {
+        self.replace_child(parent, sibling, node);
+        self.refit_ancestors(parent);
+      },
+      None => self.root = Some(node),
+    }
+    handle
+  }
+
+  // 既に削除されたハンドルなら None
+  pub fn remove(&mut self, handle: Handle) -> Option<Box<dyn Shape>> {
+    self.get(handle)?;
+    let slot = &mut self.slots[handle.index];
+    let shape = slot.shape.take();
+    slot.generation = slot.generation.wrapping_add(1);
+    let leaf = slot.leaf;
+    self.free_slots.push(handle.index);
+    self.free_nodes.push(leaf);
+    let parent = match self.nodes[leaf].parent {
+      Some(parent) => parent,
+      None => {
+        self.root = None;
+        return shape;
+      },
+    };
+    // 親を取り除き兄弟を祖父母に繋ぎ直す
+    let (left, right) = self.nodes[parent].children.unwrap();
+    let sibling = if left == leaf { right } else { left };
+    let grandparent = self.nodes[parent].parent;
+    self.nodes[sibling].parent = grandparent;
+    self.free_nodes.push(parent);
+    match grandparent {
+      Some(grandparent) => {
+        self.replace_child(grandparent, parent, sibling);
+        self.refit_ancestors(grandparent);
+      },
+      None => self.root = Some(sibling),
+    }
+    shape
+  }
+
+  pub fn get(&self, handle: Handle) -> Option<&dyn Shape> {
+    self.slots.get(handle.index)
+      .filter( |v| v.generation == handle.generation )
+      .and_then( |v| v.shape.as_deref() )
+  }
+
+  // Intersection::index の位置にある形状の現在のハンドル
+  pub fn handle(&self, index: usize) -> Option<Handle> {
+    self.slots.get(index).filter( |v| v.shape.is_some() ).map( |v| Handle {
+      index,
+      generation: v.generation,
+    })
+  }
+
+  pub fn len(&self) -> usize {
+    self.slots.len() - self.free_slots.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.root.is_none()
+  }
+
+  pub fn node_count(&self) -> usize {
+    self.nodes.len() - self.free_nodes.len()
+  }
+
+  // 木全体のSAHコスト
+  pub fn cost(&self) -> f32 {
+    let root = match self.root {
+      Some(root) => root,
+      None => return 0.0,
+    };
+    let s_a = self.nodes[root].aabb.surface_area();
+    let mut stack = vec![root];
+    let mut cost = 0.0;
+    while let Some(index) = stack.pop() {
+      let node = &self.nodes[index];
+      let t = match node.children {
+        Some((left, right)) => {
+          stack.push(left);
+          stack.push(right);
+          2.0 * self.options.traversal_cost
+        },
+        None => self.options.intersection_cost,
+      };
+      cost += node.aabb.surface_area() / s_a * t;
+    }
+    cost
+  }
+
+  // 木の回転 (Kopta et al. 2012) を繰り返してSAHコストを下げ、前後のコストを返す
+  pub fn optimize(&mut self) -> (f32, f32) {
+    let before = self.cost();
+    let root = match self.root {
+      Some(root) => root,
+      None => return (before, before),
+    };
+    for _ in 0..16 {
+      // 子が親より先に来る順に回転する
+      let mut order = Vec::with_capacity(self.node_count());
+      let mut stack = vec![root];
+      while let Some(index) = stack.pop() {
+        order.push(index);
+        if let Some((left, right)) = self.nodes[index].children {
+          stack.push(left);
+          stack.push(right);
+        }
+      }
+      let mut rotated = false;
+      for &index in order.iter().rev() {
+        rotated |= self.rotate(index);
+      }
+      if !rotated {
+        break;
+      }
+    }
+    (before, self.cost())
+  }
+
+  fn allocate(&mut self, node: Node) -> usize {
+    match self.free_nodes.pop() {
+      Some(index) => {
+        self.nodes[index] = node;
+        index
+      },
+      None => {
+        self.nodes.push(node);
+        self.nodes.len() - 1
+      },
+    }
+  }
+
+  fn replace_child(&mut self, parent: usize, old: usize, new: usize) {
+    let (left, right) = self.nodes[parent].children.unwrap();
+    self.nodes[parent].children = Some(if left == old { (new, right) } else { (left, new) });
+  }
+
+  // 祖先の AABB を更新しながら、それぞれで回転して木の偏りを抑える
+  fn refit_ancestors(&mut self, mut index: usize) {
+    loop {
+      let (left, right) = self.nodes[index].children.unwrap();
+      self.nodes[index].aabb = self.nodes[left].aabb.merge_with(&self.nodes[right].aabb);
+      self.rotate(index);
+      match self.nodes[index].parent {
+        Some(parent) => index = parent,
+        None => return,
+      }
+    }
+  }
+
+  // 一方の子ともう一方の子の子を入れ替えて表面積が減るなら回転し、回転したかを返す
+  // (index の AABB は変わらない)
+  fn rotate(&mut self, index: usize) -> bool {
+    let (left, right) = match self.nodes[index].children {
+      Some(v) => v,
+      None => return false,
+    };
+    let best = best_rotation(left, right, |i| self.nodes[i].children, |i| &self.nodes[i].aabb);
+    match best {
+      Some((a, b, swap, keep, aabb)) => {
+        self.nodes[index].children = Some(if a == left { (swap, b) } else { (b, swap) });
+        self.nodes[b].children = Some((a, keep));
+        self.nodes[b].aabb = aabb;
+        self.nodes[swap].parent = Some(index);
+        self.nodes[a].parent = Some(b);
+        true
+      },
+      None => false,
+    }
+  }
+
+  // 分枝限定法による兄弟の探索 (Bittner et al. 2012)
+  // コストは兄弟と結合したAABBの表面積と、祖先のAABBの表面積の増分の和
+  fn find_sibling(&self, root: usize, aabb: &AABB) -> usize {
+    let s = aabb.surface_area();
+    let mut best = root;
+    let mut best_cost = f32::INFINITY;
+    // 祖先から引き継いだ表面積の増分が小さい順に調べる
+    let mut queue = BinaryHeap::new();
+    queue.push(Reverse((OrderedFloat(0.0), root)));
+    while let Some(Reverse((OrderedFloat(inherited), index))) = queue.pop() {
+      // これ以降の候補はどれも現在の最良を下回れない
+      if s + inherited >= best_cost {
+        break;
+      }
+      let node = &self.nodes[index];
+      let direct = node.aabb.merge_with(aabb).surface_area();
+      if direct + inherited < best_cost {
+        best = index;
+        best_cost = direct + inherited;
+      }
+      if let Some((left, right)) = node.children {
+        let inherited = inherited + direct - node.aabb.surface_area();
+        if s + inherited < best_cost {
+          queue.push(Reverse((OrderedFloat(inherited), left)));
+          queue.push(Reverse((OrderedFloat(inherited), right)));
+        }
+      }
+    }
+    best
+  }
+}
+
+impl Default for DynamicBVH {
+  fn default() -> DynamicBVH {
+    Self::new()
+  }
+}
+
+impl Shape for DynamicBVH {
+  fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+    let root = self.root?;
+    let mut closest: Option<Intersection> = None;
+    // 木が深くなっても再帰しないように明示的なスタックで辿る
+    let mut stack = match self.nodes[root].aabb.intersect_distance(ray) {
+      Some(t) => vec![(root, t)],
+      None => return None,
+    };
+    while let Some((index, t)) = stack.pop() {
+      // 既知の最近交差より遠いノードは枝刈り
+      if closest.as_ref().is_some_and( |c| t > c.distance ) {
+        continue;
+      }
+      let node = &self.nodes[index];
+      let (left, right) = match node.children {
+        Some(children) => children,
+        None => {
+          // 既知の最近交差より遠い交差は探さない
+          let t_max = closest.as_ref().map_or(ray.t_max, |c| c.distance);
+          let ray = &Ray::with_interval(ray.origin, ray.direction, ray.t_min, t_max);
+          if let Some(mut v) = self.slots[node.slot].shape.as_ref().unwrap().intersect(ray) {
+            if closest.as_ref().is_none_or( |c| v.distance < c.distance ) {
+              v.index = node.slot;
+              closest = Some(v);
+            }
+          }
+          continue;
+        },
+      };
+      let left_t = self.nodes[left].aabb.intersect_distance(ray);
+      let right_t = self.nodes[right].aabb.intersect_distance(ray);
+      // 進入距離の近い子を後に積んで先に辿る
+      let order = match (left_t, right_t) {
+        (Some(l), Some(r)) if r < l => [(left_t, left), (right_t, right)],
+        _ => [(right_t, right), (left_t, left)],
+      };
+      for &(t, child) in order.iter() {
+        if let Some(t) = t {
+          stack.push((child, t));
+        }
+      }
+    }
+    closest
+  }
+
+  fn occluded(&self, ray: &Ray, t_max: f32) -> bool {
+    let mut stack = self.root.into_iter().collect::<Vec<_>>();
+    while let Some(index) = stack.pop() {
+      let node = &self.nodes[index];
+      match node.aabb.intersect_distance(ray) {
+        Some(t) if t < t_max => (),
+        _ => continue,
+      }
+      match node.children {
+        Some((left, right)) => {
+          stack.push(right);
+          stack.push(left);
+        },
+        None => {
+          if self.slots[node.slot].shape.as_ref().unwrap().occluded(ray, t_max) {
+            return true;
+          }
+        },
+      }
+    }
+    false
+  }
+
+  fn aabb(&self) -> &AABB {
+    match self.root {
+      Some(root) => &self.nodes[root].aabb,
+      None => &self.empty,
+    }
+  }
+}
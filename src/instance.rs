@@ -0,0 +1,86 @@
+use crate::intersection::Intersection;
+use crate::shape::*;
+use crate::ray::Ray;
+use crate::math::vector::*;
+use crate::math::matrix::*;
+use crate::aabb::AABB;
+
+// 任意の形状を物体座標系からワールド座標系への変換付きで配置したもの
+pub struct Instance<S: Shape> {
+  pub shape: S,
+  transform: Matrix4,
+  inverse: Matrix4,
+  // 法線の変換には逆行列の転置を使う
+  normal_transform: Matrix4,
+  aabb: AABB,
+}
+
+impl<S: Shape> Instance<S> {
+  // 変換が逆行列を持たないときは None
+  pub fn new(shape: S, transform: Matrix4) -> Option<Instance<S>> {
+    let inverse = transform.inverse()?;
+    let normal_transform = inverse.transpose();
+    let aabb = Self::transform_aabb(shape.aabb(), &transform);
+    Some(Instance {
+      shape,
+      transform,
+      inverse,
+      normal_transform,
+      aabb,
+    })
+  }
+
+  pub fn transform(&self) -> &Matrix4 {
+    &self.transform
+  }
+
+  // 8 頂点を変換してそれらを囲む AABB を求める
+  fn transform_aabb(aabb: &AABB, transform: &Matrix4) -> AABB {
+    let corners = (0..8).map( |i| {
+      transform.transform_point(Vector3::new(
+        if i & 1 == 0 { aabb.min.x } else { aabb.max.x },
+        if i & 2 == 0 { aabb.min.y } else { aabb.max.y },
+        if i & 4 == 0 { aabb.min.z } else { aabb.max.z },
+      ))
+    }).collect::<Vec<_>>();
+    let min = corners.iter().fold(corners[0], |m, c| {
+      Vector3::new(m.x.min(c.x), m.y.min(c.y), m.z.min(c.z))
+    });
+    let max = corners.iter().fold(corners[0], |m, c| {
+      Vector3::new(m.x.max(c.x), m.y.max(c.y), m.z.max(c.z))
+    });
+    AABB {
+      min,
+      max,
+      center: (min + max) / 2.0,
+    }
+  }
+
+  // 方向ベクトルを正規化しないことで物体座標系でも距離 t がそのまま使える
+  fn local_ray(&self, ray: &Ray) -> Ray {
+    Ray::with_interval(
+      self.inverse.transform_point(ray.origin),
+      self.inverse.transform_vector(ray.direction),
+      ray.t_min,
+      ray.t_max,
+    )
+  }
+}
+
+impl<S: Shape> Shape for Instance<S> {
+  fn aabb(&self) -> &AABB {
+    &self.aabb
+  }
+
+  fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+    let mut i = self.shape.intersect(&self.local_ray(ray))?;
+    i.position = ray.origin + ray.direction * i.distance;
+    i.normal = self.normal_transform.transform_vector(i.normal).normalize();
+    i.shading_normal = self.normal_transform.transform_vector(i.shading_normal).normalize();
+    Some(i)
+  }
+
+  fn occluded(&self, ray: &Ray, t_max: f32) -> bool {
+    self.shape.occluded(&self.local_ray(ray), t_max)
+  }
+}
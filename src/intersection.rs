@@ -1,7 +1,18 @@
-use math::vector::Vector3;
+use crate::math::vector::Vector3;
 
 pub struct Intersection {
   pub position: Vector3,
   pub distance: f32,
   pub normal: Vector3,
+  // 交差したプリミティブのリスト中のインデックス
+  pub index: usize,
+  // 交差したインスタンスの ID (シーン以外では 0)
+  pub instance: usize,
+  // 重心座標 (position = (1 - u - v) * p0 + u * p1 + v * p2)
+  pub u: f32,
+  pub v: f32,
+  // 頂点法線を補間したシェーディング法線 (無ければ normal と同じ)
+  pub shading_normal: Vector3,
+  // テクスチャ座標 (無ければ (u, v))
+  pub texcoord: (f32, f32),
 }
@@ -0,0 +1,18 @@
+pub mod math;
+pub mod shape;
+pub mod triangle;
+pub mod mesh;
+pub mod sphere;
+pub mod quad;
+pub mod disk;
+pub mod cylinder;
+pub mod capsule;
+pub mod instance;
+pub mod scene;
+pub mod ray;
+pub mod intersection;
+pub mod aabb;
+pub mod constant;
+pub mod bvh;
+pub mod builder;
+pub mod dynamic;
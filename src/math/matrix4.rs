@@ -1,11 +1,23 @@
 use std::ops::{Neg, Add, Sub, Mul};
 use super::vector::*;
 
+#[derive(Clone, Debug)]
 pub struct Matrix4 {
   v: Vec<f32>,
 }
 
 impl Matrix4 {
+  pub fn identity() -> Matrix4 {
+    Matrix4 {
+      v: vec![
+        1.0, 0.0, 0.0, 0.0,
+        0.0, 1.0, 0.0, 0.0,
+        0.0, 0.0, 1.0, 0.0,
+        0.0, 0.0, 0.0, 1.0,
+      ]
+    }
+  }
+
   pub fn translate(v: Vector3) -> Matrix4 {
     Matrix4 {
       v: vec![
@@ -43,11 +55,71 @@ impl Matrix4 {
   }
 
   pub fn col(&self, x: usize) -> Vector4 {
-    (0..4).map( |i| self.v[x + i * 4]).collect::<Vec<_>>().into()
+    Vector4::new(self.v[x], self.v[x + 4], self.v[x + 8], self.v[x + 12])
   }
 
   pub fn row(&self, y: usize) -> Vector4 {
-    (0..4).map( |i| self.v[4 * y + i]).collect::<Vec<_>>().into()
+    Vector4::new(self.v[4 * y], self.v[4 * y + 1], self.v[4 * y + 2], self.v[4 * y + 3])
+  }
+
+  pub fn transpose(&self) -> Matrix4 {
+    Matrix4 {
+      v: (0..4).flat_map( |y| (0..4).map( move |x| self.v[4 * x + y] ) ).collect()
+    }
+  }
+
+  // 特異な (逆行列を持たない) 場合は None
+  pub fn inverse(&self) -> Option<Matrix4> {
+    // ガウス・ジョルダン法 (部分ピボット選択)
+    let mut a = self.v.clone();
+    // ピボットがこれ以下なら特異とみなす
+    let threshold = a.iter().fold(0.0f32, |m, v| m.max(v.abs())) * f32::EPSILON;
+    let mut inv = Self::identity().v;
+    for c in 0..4 {
+      let p = (c..4).max_by( |&i, &j| {
+        a[4 * i + c].abs().partial_cmp(&a[4 * j + c].abs()).unwrap()
+      }).unwrap();
+      for k in 0..4 {
+        a.swap(4 * c + k, 4 * p + k);
+        inv.swap(4 * c + k, 4 * p + k);
+      }
+      let d = a[4 * c + c];
+      if d.abs() <= threshold || !d.is_finite() {
+        return None;
+      }
+      for k in 0..4 {
+        a[4 * c + k] /= d;
+        inv[4 * c + k] /= d;
+      }
+      for r in (0..4).filter( |&r| r != c ) {
+        let f = a[4 * r + c];
+        for k in 0..4 {
+          a[4 * r + k] -= f * a[4 * c + k];
+          inv[4 * r + k] -= f * inv[4 * c + k];
+        }
+      }
+    }
+    Some(Matrix4 { v: inv })
+  }
+
+  // 同次座標 w = 1 として変換
+  pub fn transform_point(&self, p: Vector3) -> Vector3 {
+    let m = &self.v;
+    Vector3::new(
+      m[0] * p.x + m[1] * p.y + m[2] * p.z + m[3],
+      m[4] * p.x + m[5] * p.y + m[6] * p.z + m[7],
+      m[8] * p.x + m[9] * p.y + m[10] * p.z + m[11],
+    )
+  }
+
+  // 同次座標 w = 0 として変換 (平行移動を無視)
+  pub fn transform_vector(&self, v: Vector3) -> Vector3 {
+    let m = &self.v;
+    Vector3::new(
+      m[0] * v.x + m[1] * v.y + m[2] * v.z,
+      m[4] * v.x + m[5] * v.y + m[6] * v.z,
+      m[8] * v.x + m[9] * v.y + m[10] * v.z,
+    )
   }
 }
 
@@ -1,11 +1,11 @@
 use std::ops::{Div};
 
 pub trait Dot {
-  fn dot(self, Self) -> f32;
+  fn dot(self, rhs: Self) -> f32;
 }
 
 pub trait Cross {
-  fn cross(self, Self) -> Self;
+  fn cross(self, rhs: Self) -> Self;
 }
 
 pub trait Norm {
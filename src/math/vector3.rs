@@ -12,7 +12,18 @@ pub struct Vector3 {
 
 impl Vector3 {
   pub fn new(x: f32, y: f32, z: f32) -> Vector3 {
-    Vector3 { x: x, y: y, z: z }
+    Vector3 { x, y, z }
+  }
+
+  // 自身 (単位ベクトル) に直交する2軸 (Duff et al. 2017)
+  pub fn orthonormal_basis(self) -> (Vector3, Vector3) {
+    let sign = 1.0f32.copysign(self.z);
+    let a = -1.0 / (sign + self.z);
+    let b = self.x * self.y * a;
+    (
+      Vector3::new(1.0 + sign * self.x * self.x * a, sign * b, -sign * self.x),
+      Vector3::new(b, sign + self.y * self.y * a, -self.y),
+    )
   }
 }
 
@@ -22,7 +33,7 @@ impl Zero for Vector3 {
   }
 }
 
-impl<'a> From<&'a [f32]> for Vector3 {
+impl From<&[f32]> for Vector3 {
   fn from(v: &[f32]) -> Vector3 {
     if v.len() > 4 { panic!("Slice must have length more than 3.") }
     Vector3 { x: v[0], y: v[1], z: v[2] }
@@ -36,9 +47,9 @@ impl From<Vec<f32>> for Vector3 {
   }
 }
 
-impl Into<[f32; 3]> for Vector3 {
-  fn into(self) -> [f32; 3] {
-    [self.x, self.y, self.z]
+impl From<Vector3> for [f32; 3] {
+  fn from(val: Vector3) -> Self {
+    [val.x, val.y, val.z]
   }
 }
 
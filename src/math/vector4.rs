@@ -13,7 +13,7 @@ pub struct Vector4 {
 
 impl Vector4 {
   pub fn new(x: f32, y: f32, z: f32, w: f32) -> Vector4 {
-    Vector4 { x: x, y: y, z: z, w: w }
+    Vector4 { x, y, z, w }
   }
 }
 
@@ -23,7 +23,7 @@ impl Zero for Vector4 {
   }
 }
 
-impl<'a> From<&'a [f32]> for Vector4 {
+impl From<&[f32]> for Vector4 {
   fn from(v: &[f32]) -> Vector4 {
     if v.len() > 4 { panic!("Slice must have length more than 4.") }
     Vector4 { x: v[0], y: v[1], z: v[2], w: v[3] }
@@ -43,9 +43,9 @@ impl From<Vector3> for Vector4 {
   }
 }
 
-impl Into<[f32; 4]> for Vector4 {
-  fn into(self) -> [f32; 4] {
-    [self.x, self.y, self.z, self.w]
+impl From<Vector4> for [f32; 4] {
+  fn from(val: Vector4) -> Self {
+    [val.x, val.y, val.z, val.w]
   }
 }
 
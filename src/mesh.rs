@@ -0,0 +1,164 @@
+use crate::intersection::Intersection;
+use crate::shape::*;
+use crate::ray::Ray;
+use crate::math::vector::*;
+use crate::aabb::AABB;
+use crate::triangle::Triangle;
+use crate::bvh::{Hierarchy, BuildOptions, Split};
+use crate::builder::Sbvh;
+
+// 頂点と面のインデックスを共有する三角形メッシュ
+pub struct TriangleMesh {
+  positions: Vec<Vector3>,
+  // 3つずつ1つの面を表す
+  indices: Vec<u32>,
+  // 頂点ごとの法線とテクスチャ座標 (positions と同じインデックスで参照)
+  normals: Option<Vec<Vector3>>,
+  texcoords: Option<Vec<(f32, f32)>>,
+  hierarchy: Hierarchy,
+}
+
+impl TriangleMesh {
+  pub fn new(positions: Vec<Vector3>, indices: Vec<u32>) -> TriangleMesh {
+    Self::with_options(positions, indices, BuildOptions::default())
+  }
+
+  pub fn with_options(
+    positions: Vec<Vector3>,
+    indices: Vec<u32>,
+    options: BuildOptions,
+  ) -> TriangleMesh {
+    assert!(indices.len().is_multiple_of(3), "Index buffer length must be a multiple of 3.");
+    assert!(indices.iter().all( |&i| (i as usize) < positions.len() ), "Index buffer must refer to existing vertices.");
+    let aabbs = Self::face_aabbs(&positions, &indices);
+    let hierarchy = match options.split {
+      // 空間分割では面に沿って参照を切り分ける
+      Split::Sbvh { budget } => {
+        let clip = |f: usize, aabb: &AABB, axis, position| {
+          let (p0, p1, p2) = Self::vertices(&positions, &indices, f);
+          Triangle::split_aabb(p0, p1, p2, aabb, axis, position)
+        };
+        Hierarchy::with_builder(aabbs, &Sbvh { budget, clip }, options)
+      },
+      _ => Hierarchy::new(aabbs, options),
+    };
+    TriangleMesh {
+      positions,
+      indices,
+      normals: None,
+      texcoords: None,
+      hierarchy,
+    }
+  }
+
+  pub fn with_normals(mut self, normals: Vec<Vector3>) -> TriangleMesh {
+    assert_eq!(normals.len(), self.positions.len(), "Normals must be given per vertex.");
+    self.normals = Some(normals);
+    self
+  }
+
+  pub fn with_texcoords(mut self, texcoords: Vec<(f32, f32)>) -> TriangleMesh {
+    assert_eq!(texcoords.len(), self.positions.len(), "Texcoords must be given per vertex.");
+    self.texcoords = Some(texcoords);
+    self
+  }
+
+  pub fn positions(&self) -> &[Vector3] {
+    &self.positions
+  }
+
+  // 頂点を動かした後は refit を呼ぶ
+  pub fn positions_mut(&mut self) -> &mut [Vector3] {
+    &mut self.positions
+  }
+
+  // 面の接続関係を保ったまま、現在の頂点位置に階層を合わせる
+  pub fn refit(&mut self) {
+    let aabbs = Self::face_aabbs(&self.positions, &self.indices).collect::<Vec<_>>();
+    self.hierarchy.refit(&aabbs);
+  }
+
+  // 木の回転によってSAHコストを下げ、前後のコストを返す (refit を繰り返した後に有効)
+  pub fn optimize(&mut self) -> (f32, f32) {
+    self.hierarchy.optimize()
+  }
+
+  pub fn indices(&self) -> &[u32] {
+    &self.indices
+  }
+
+  pub fn face_count(&self) -> usize {
+    self.indices.len() / 3
+  }
+
+  pub fn hierarchy(&self) -> &Hierarchy {
+    &self.hierarchy
+  }
+
+  fn face_aabbs<'a>(positions: &'a [Vector3], indices: &'a [u32]) -> impl Iterator<Item = AABB> + 'a {
+    (0..indices.len() / 3).map( move |f| {
+      let (p0, p1, p2) = Self::vertices(positions, indices, f);
+      Triangle::aabb(p0, p1, p2)
+    })
+  }
+
+  fn vertices(positions: &[Vector3], indices: &[u32], f: usize) -> (Vector3, Vector3, Vector3) {
+    (
+      positions[indices[f * 3] as usize],
+      positions[indices[f * 3 + 1] as usize],
+      positions[indices[f * 3 + 2] as usize],
+    )
+  }
+
+  fn intersect_face(&self, f: usize, ray: &Ray) -> Option<Intersection> {
+    let (p0, p1, p2) = Self::vertices(&self.positions, &self.indices, f);
+    let (t, u, v) = Triangle::hit(p0, p1, p2, ray)?;
+    let normal = Triangle::normal(p0, p1, p2);
+    let (i0, i1, i2) = (
+      self.indices[f * 3] as usize,
+      self.indices[f * 3 + 1] as usize,
+      self.indices[f * 3 + 2] as usize,
+    );
+    // 重心座標で頂点属性を補間
+    let w = 1.0 - u - v;
+    let shading_normal = match self.normals {
+      Some(ref n) => (n[i0] * w + n[i1] * u + n[i2] * v).normalize(),
+      None => normal,
+    };
+    let texcoord = match self.texcoords {
+      Some(ref c) => (
+        c[i0].0 * w + c[i1].0 * u + c[i2].0 * v,
+        c[i0].1 * w + c[i1].1 * u + c[i2].1 * v,
+      ),
+      None => (u, v),
+    };
+    Some(Intersection {
+      distance: t,
+      normal,
+      position: ray.origin + ray.direction * t,
+      index: f,
+      instance: 0,
+      u,
+      v,
+      shading_normal,
+      texcoord,
+    })
+  }
+}
+
+impl Shape for TriangleMesh {
+  fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+    self.hierarchy.intersect(ray, |f, ray| self.intersect_face(f, ray))
+  }
+
+  fn occluded(&self, ray: &Ray, t_max: f32) -> bool {
+    self.hierarchy.occluded(ray, t_max, |f, ray, t_max| {
+      let (p0, p1, p2) = Self::vertices(&self.positions, &self.indices, f);
+      Triangle::hit(p0, p1, p2, ray).is_some_and( |(t, _, _)| t < t_max )
+    })
+  }
+
+  fn aabb(&self) -> &AABB {
+    self.hierarchy.aabb()
+  }
+}
@@ -0,0 +1,79 @@
+use crate::intersection::Intersection;
+use crate::shape::*;
+use crate::constant::*;
+use crate::ray::Ray;
+use crate::math::vector::*;
+use crate::aabb::AABB;
+
+// p を頂点とし e1, e2 を2辺とする平行四辺形
+pub struct Quad {
+  pub p: Vector3,
+  pub e1: Vector3,
+  pub e2: Vector3,
+  aabb: AABB,
+  normal: Vector3,
+}
+
+impl Quad {
+  pub fn new(p: Vector3, e1: Vector3, e2: Vector3) -> Quad {
+    let corners = [p, p + e1, p + e2, p + e1 + e2];
+    let min = corners.iter().fold(corners[0], |m, c| {
+      Vector3::new(m.x.min(c.x), m.y.min(c.y), m.z.min(c.z))
+    });
+    let max = corners.iter().fold(corners[0], |m, c| {
+      Vector3::new(m.x.max(c.x), m.y.max(c.y), m.z.max(c.z))
+    });
+    Quad {
+      p,
+      e1,
+      e2,
+      aabb: AABB {
+        min,
+        max,
+        center: (min + max) / 2.0,
+      },
+      normal: e1.cross(e2).normalize(),
+    }
+  }
+}
+
+impl Shape for Quad {
+  fn aabb(&self) -> &AABB {
+    &self.aabb
+  }
+
+  fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+    // 三角形と同じく Möller–Trumbore で (u, v) を求め、u + v の制限だけ外す
+    let pv = ray.direction.cross(self.e2);
+    let det = self.e1.dot(pv);
+    if det.abs() < EPS {
+      return None;
+    }
+    let invdet = 1.0 / det;
+    let tv = ray.origin - self.p;
+    let u = tv.dot(pv) * invdet;
+    if !(0.0..=1.0).contains(&u) {
+      return None;
+    }
+    let qv = tv.cross(self.e1);
+    let v = ray.direction.dot(qv) * invdet;
+    if !(0.0..=1.0).contains(&v) {
+      return None;
+    }
+    let t = self.e2.dot(qv) * invdet;
+    if t < ray.t_min || t > ray.t_max {
+      return None;
+    }
+    Some(Intersection {
+      distance: t,
+      normal: self.normal,
+      position: ray.origin + ray.direction * t,
+      index: 0,
+      instance: 0,
+      u,
+      v,
+      shading_normal: self.normal,
+      texcoord: (u, v),
+    })
+  }
+}
@@ -1,6 +1,26 @@
-use math::vector::Vector3;
+use std::f32;
+use crate::math::vector::Vector3;
+use crate::constant::EPS;
 
 pub struct Ray {
   pub origin: Vector3,
   pub direction: Vector3,
+  // 交差を受け付ける距離の範囲
+  pub t_min: f32,
+  pub t_max: f32,
+}
+
+impl Ray {
+  pub fn new(origin: Vector3, direction: Vector3) -> Ray {
+    Self::with_interval(origin, direction, EPS, f32::INFINITY)
+  }
+
+  pub fn with_interval(origin: Vector3, direction: Vector3, t_min: f32, t_max: f32) -> Ray {
+    Ray {
+      origin,
+      direction,
+      t_min,
+      t_max,
+    }
+  }
 }
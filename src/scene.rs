@@ -0,0 +1,105 @@
+use crate::intersection::Intersection;
+use crate::shape::*;
+use crate::ray::Ray;
+use crate::math::matrix::*;
+use crate::aabb::AABB;
+use crate::instance::Instance;
+use crate::bvh::{BuildOptions, Hierarchy};
+use std::sync::Arc;
+
+// 2段階の階層
+// 下位: 形状ごとの階層 (TriangleMesh など自身で階層を持つもの)
+// 上位: 変換されたインスタンスの AABB に対する階層 (インスタンスが動いたら build し直す)
+pub struct Scene {
+  geometries: Vec<Arc<dyn Shape>>,
+  instances: Vec<Instance<Arc<dyn Shape>>>,
+  // 各インスタンスが参照する形状の ID
+  instance_geometries: Vec<usize>,
+  options: BuildOptions,
+  hierarchy: Hierarchy,
+}
+
+impl Scene {
+  pub fn new() -> Scene {
+    Self::with_options(BuildOptions::default())
+  }
+
+  pub fn with_options(options: BuildOptions) -> Scene {
+    Scene {
+      geometries: Vec::new(),
+      instances: Vec::new(),
+      instance_geometries: Vec::new(),
+      options,
+      hierarchy: Hierarchy::new(Vec::new(), options),
+    }
+  }
+
+  // 形状を登録して ID を返す
+  pub fn add_geometry<S: Shape + 'static>(&mut self, geometry: S) -> usize {
+    self.geometries.push(Arc::new(geometry));
+    self.geometries.len() - 1
+  }
+
+  // 形状を配置してインスタンス ID を返す (build するまで交差判定には反映されない)
+  // 変換が逆行列を持たないときは配置せずに None
+  pub fn add_instance(&mut self, geometry: usize, transform: Matrix4) -> Option<usize> {
+    self.instances.push(Instance::new(self.geometries[geometry].clone(), transform)?);
+    self.instance_geometries.push(geometry);
+    Some(self.instances.len() - 1)
+  }
+
+  // インスタンスを移動する (build するまで交差判定には反映されない)
+  // 変換が逆行列を持たないときは元の配置のまま None
+  pub fn set_transform(&mut self, instance: usize, transform: Matrix4) -> Option<()> {
+    let geometry = self.geometries[self.instance_geometries[instance]].clone();
+    self.instances[instance] = Instance::new(geometry, transform)?;
+    Some(())
+  }
+
+  pub fn geometry(&self, instance: usize) -> usize {
+    self.instance_geometries[instance]
+  }
+
+  pub fn instance(&self, instance: usize) -> &Instance<Arc<dyn Shape>> {
+    &self.instances[instance]
+  }
+
+  pub fn instance_count(&self) -> usize {
+    self.instances.len()
+  }
+
+  // 上位の階層を作り直す
+  pub fn build(&mut self) {
+    self.hierarchy = Hierarchy::new(self.instances.iter().map( |v| v.aabb().clone() ), self.options);
+  }
+
+  pub fn hierarchy(&self) -> &Hierarchy {
+    &self.hierarchy
+  }
+}
+
+impl Default for Scene {
+  fn default() -> Scene {
+    Self::new()
+  }
+}
+
+impl Shape for Scene {
+  fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+    // index は下位の階層が返すプリミティブ ID をそのまま残す
+    self.hierarchy.intersect(ray, |i, ray| {
+      self.instances[i].intersect(ray).map( |mut v| {
+        v.instance = i;
+        v
+      })
+    })
+  }
+
+  fn occluded(&self, ray: &Ray, t_max: f32) -> bool {
+    self.hierarchy.occluded(ray, t_max, |i, ray, t_max| self.instances[i].occluded(ray, t_max))
+  }
+
+  fn aabb(&self) -> &AABB {
+    self.hierarchy.aabb()
+  }
+}
@@ -1,8 +1,57 @@
-use intersection::Intersection;
-use ray::Ray;
-use aabb::AABB;
+use std::sync::Arc;
+use crate::intersection::Intersection;
+use crate::ray::Ray;
+use crate::aabb::AABB;
 
 pub trait Shape {
-  fn intersect(&self, &Ray) -> Option<Intersection>;
+  fn intersect(&self, ray: &Ray) -> Option<Intersection>;
   fn aabb(&self) -> &AABB;
+
+  // t_max より手前で交差するか (最初に見つかった時点で打ち切ってよい)
+  fn occluded(&self, ray: &Ray, t_max: f32) -> bool {
+    self.intersect(ray).is_some_and(|i| i.distance < t_max)
+  }
+
+  // 形状のうち aabb の範囲にある部分を、axis 軸に垂直な position の平面で切り分けた両側の AABB
+  // (SBVH の空間分割で使う。形状に沿って切れないものは aabb 自体を切り分ける)
+  fn split_aabb(&self, aabb: &AABB, axis: usize, position: f32) -> (AABB, AABB) {
+    aabb.split(axis, position)
+  }
+}
+
+// 参照や共有ポインタ越しでも Shape として扱えるようにする (インスタンスで形状を共有するため)
+impl<T: Shape + ?Sized> Shape for &T {
+  fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+    (**self).intersect(ray)
+  }
+
+  fn aabb(&self) -> &AABB {
+    (**self).aabb()
+  }
+
+  fn occluded(&self, ray: &Ray, t_max: f32) -> bool {
+    (**self).occluded(ray, t_max)
+  }
+
+  fn split_aabb(&self, aabb: &AABB, axis: usize, position: f32) -> (AABB, AABB) {
+    (**self).split_aabb(aabb, axis, position)
+  }
+}
+
+impl<T: Shape + ?Sized> Shape for Arc<T> {
+  fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+    (**self).intersect(ray)
+  }
+
+  fn aabb(&self) -> &AABB {
+    (**self).aabb()
+  }
+
+  fn occluded(&self, ray: &Ray, t_max: f32) -> bool {
+    (**self).occluded(ray, t_max)
+  }
+
+  fn split_aabb(&self, aabb: &AABB, axis: usize, position: f32) -> (AABB, AABB) {
+    (**self).split_aabb(aabb, axis, position)
+  }
 }
@@ -0,0 +1,78 @@
+use crate::intersection::Intersection;
+use crate::shape::*;
+use crate::constant::*;
+use crate::ray::Ray;
+use crate::math::vector::*;
+use crate::aabb::AABB;
+
+pub struct Sphere {
+  pub center: Vector3,
+  pub radius: f32,
+  aabb: AABB,
+}
+
+impl Sphere {
+  pub fn new(center: Vector3, radius: f32) -> Sphere {
+    let r = Vector3::new(radius, radius, radius);
+    Sphere {
+      center,
+      radius,
+      aabb: AABB {
+        min: center - r,
+        max: center + r,
+        center,
+      },
+    }
+  }
+
+  // 球面との2つの交差距離 (小さい順)
+  pub(crate) fn roots(center: Vector3, radius: f32, ray: &Ray) -> Option<(f32, f32)> {
+    // 桁落ちを避けた二次方程式の解法 (Ray Tracing Gems, Chapter 7)
+    let f = ray.origin - center;
+    let a = ray.direction.dot(ray.direction);
+    let b = -f.dot(ray.direction);
+    // 中心からレイへの垂線の長さで判別式を求める
+    let l = f + ray.direction * (b / a);
+    let discriminant = radius * radius - l.dot(l);
+    if discriminant < 0.0 {
+      return None;
+    }
+    let c = f.dot(f) - radius * radius;
+    let q = b + b.signum() * (a * discriminant).sqrt();
+    let (t0, t1) = (c / q, q / a);
+    Some(if t0 < t1 { (t0, t1) } else { (t1, t0) })
+  }
+}
+
+impl Shape for Sphere {
+  fn aabb(&self) -> &AABB {
+    &self.aabb
+  }
+
+  fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+    let (t0, t1) = Self::roots(self.center, self.radius, ray)?;
+    let t = if t0 >= ray.t_min && t0 <= ray.t_max {
+      t0
+    } else if t1 >= ray.t_min && t1 <= ray.t_max {
+      t1
+    } else {
+      return None;
+    };
+    let position = ray.origin + ray.direction * t;
+    let normal = (position - self.center) / self.radius;
+    // 球面座標によるパラメータ化
+    let u = (normal.z.atan2(normal.x) + PI) / (2.0 * PI);
+    let v = normal.y.clamp(-1.0, 1.0).acos() / PI;
+    Some(Intersection {
+      distance: t,
+      normal,
+      position,
+      index: 0,
+      instance: 0,
+      u,
+      v,
+      shading_normal: normal,
+      texcoord: (u, v),
+    })
+  }
+}
@@ -1,13 +1,10 @@
-extern crate test;
-extern crate rand;
-
-use intersection::Intersection;
-use shape::*;
-use constant::*;
-use ray::Ray;
-use math::vector::Vector3;
-use math::vector::*;
-use aabb::AABB;
+use crate::intersection::Intersection;
+use crate::shape::*;
+use crate::constant::*;
+use crate::ray::Ray;
+use crate::math::vector::Vector3;
+use crate::math::vector::*;
+use crate::aabb::AABB;
 
 pub struct Triangle {
   pub p0: Vector3,
@@ -24,15 +21,15 @@ impl Triangle {
     p2: Vector3,
   ) -> Triangle {
     Triangle {
-      p0: p0,
-      p1: p1,
-      p2: p2,
+      p0,
+      p1,
+      p2,
       aabb: Self::aabb(p0, p1, p2),
       normal: Self::normal(p0, p1, p2),
     }
   }
 
-  fn aabb(p0: Vector3, p1: Vector3, p2: Vector3) -> AABB {
+  pub(crate) fn aabb(p0: Vector3, p1: Vector3, p2: Vector3) -> AABB {
     let min = Vector3::new(
       p0.x.min(p1.x).min(p2.x),
       p0.y.min(p1.y).min(p2.y),
@@ -44,35 +41,68 @@ impl Triangle {
       p0.z.max(p1.z).max(p2.z),
     );
     AABB {
-      min: min,
-      max: max,
+      min,
+      max,
       center: (max + min) / 2.0,
     }
   }
 
-  fn normal(p0: Vector3, p1: Vector3, p2: Vector3) -> Vector3 {
+  pub(crate) fn normal(p0: Vector3, p1: Vector3, p2: Vector3) -> Vector3 {
     (p1 - p0).cross(p2 - p0).normalize()
   }
-}
 
-impl Shape for Triangle {
-  fn aabb(&self) -> &AABB {
-    &self.aabb
+  // 三角形のうち aabb の範囲にある部分を axis 軸に垂直な position の平面で切り分けた両側の AABB
+  pub(crate) fn split_aabb(
+    p0: Vector3,
+    p1: Vector3,
+    p2: Vector3,
+    aabb: &AABB,
+    axis: usize,
+    position: f32,
+  ) -> (AABB, AABB) {
+    let point = |p: Vector3| AABB {
+      min: p,
+      max: p,
+      center: p,
+    };
+    let mut left = AABB::empty();
+    let mut right = AABB::empty();
+    let vertices = [p0, p1, p2];
+    for k in 0..3 {
+      let (a, b) = (vertices[k], vertices[(k + 1) % 3]);
+      if a[axis] <= position {
+        left = left.merge_with(&point(a));
+      }
+      if a[axis] >= position {
+        right = right.merge_with(&point(a));
+      }
+      // 辺が平面をまたぐなら交点は両側に含まれる
+      if (a[axis] < position && position < b[axis]) || (b[axis] < position && position < a[axis]) {
+        let t = (position - a[axis]) / (b[axis] - a[axis]);
+        let p = point(a + (b - a) * t);
+        left = left.merge_with(&p);
+        right = right.merge_with(&p);
+      }
+    }
+    // 誤差で平面を越えないように切り分けた aabb に収める
+    let (left_aabb, right_aabb) = aabb.split(axis, position);
+    (left.overlap_with(&left_aabb), right.overlap_with(&right_aabb))
   }
 
-  fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+  // 交差距離と重心座標 (t, u, v)
+  pub(crate) fn hit(p0: Vector3, p1: Vector3, p2: Vector3, ray: &Ray) -> Option<(f32, f32, f32)> {
     // Möller–Trumbore intersection algorithm
-    let e1 = self.p1 - self.p0;
-    let e2 = self.p2 - self.p0;
+    let e1 = p1 - p0;
+    let e2 = p2 - p0;
     let pv = ray.direction.cross(e2);
     let det = e1.dot(pv); // クラメルの分母
     if det.abs() < EPS {
       return None;
     }
     let invdet = 1.0 / det;
-    let tv = ray.origin - self.p0;
+    let tv = ray.origin - p0;
     let u = tv.dot(pv) * invdet;
-    if u < 0.0 || u > 1.0 {
+    if !(0.0..=1.0).contains(&u) {
       return None;
     }
     let qv = tv.cross(e1);
@@ -81,14 +111,34 @@ impl Shape for Triangle {
       return None;
     }
     let t = e2.dot(qv) * invdet;
-    if t < EPS {
+    if t < ray.t_min || t > ray.t_max {
       return None;
     }
-    let p = ray.origin + ray.direction * t;
+    Some((t, u, v))
+  }
+}
+
+impl Shape for Triangle {
+  fn aabb(&self) -> &AABB {
+    &self.aabb
+  }
+
+  fn split_aabb(&self, aabb: &AABB, axis: usize, position: f32) -> (AABB, AABB) {
+    Self::split_aabb(self.p0, self.p1, self.p2, aabb, axis, position)
+  }
+
+  fn intersect(&self, ray: &Ray) -> Option<Intersection> {
+    let (t, u, v) = Self::hit(self.p0, self.p1, self.p2, ray)?;
     Some(Intersection {
       distance: t,
       normal: self.normal,
-      position: p,
+      position: ray.origin + ray.direction * t,
+      index: 0,
+      instance: 0,
+      u,
+      v,
+      shading_normal: self.normal,
+      texcoord: (u, v),
     })
   }
 }
@@ -0,0 +1,301 @@
+mod common;
+
+use common::*;
+use bvh::math::vector::*;
+use bvh::triangle::Triangle;
+use bvh::bvh::{BVH, BuildOptions, Hierarchy, Split};
+use bvh::builder::*;
+use bvh::shape::*;
+use bvh::ray::Ray;
+use bvh::aabb::AABB;
+use bvh::mesh::TriangleMesh;
+use bvh::constant::*;
+use rand::Rng;
+use std::cell::RefCell;
+
+#[test]
+fn correct_random_triangles_binned() {
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let objects = random_triangles(1000, &mut rng);
+  let bvh = BVH::with_options(&objects, BuildOptions {
+    split: Split::Binned(16),
+    ..Default::default()
+  });
+  assert_same_as_brute_force(&objects, &bvh, &mut rng);
+}
+
+#[test]
+#[should_panic(expected = "at least 2 bins")]
+fn binned_needs_two_bins() {
+  let objects = random_triangles(10, rand::XorShiftRng::new_unseeded());
+  BVH::with_options(&objects, BuildOptions {
+    split: Split::Binned(0),
+    ..Default::default()
+  });
+}
+
+#[test]
+fn correct_random_triangles_morton() {
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let objects = random_triangles(1000, &mut rng);
+  let bvh = BVH::with_options(&objects, BuildOptions {
+    split: Split::Morton,
+    ..Default::default()
+  });
+  // SAH で構築したものほど良くはならない
+  assert!(bvh.cost() >= BVH::new(&objects).cost());
+  assert_same_as_brute_force(&objects, &bvh, &mut rng);
+}
+
+#[test]
+fn correct_random_triangles_sbvh() {
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  // AABB が大きく重なる細長い三角形
+  let positions = (0..1000).flat_map( |_| {
+    let p0 = Vector3::new(
+      rng.gen_range(-10.0f32, 10.0),
+      rng.gen_range(-10.0f32, 10.0),
+      rng.gen_range(-10.0f32, 10.0),
+    );
+    let d = Vector3::new(
+      rng.gen_range(-10.0f32, 10.0),
+      rng.gen_range(-10.0f32, 10.0),
+      rng.gen_range(-10.0f32, 10.0),
+    );
+    let e = Vector3::new(
+      rng.gen_range(-0.1f32, 0.1),
+      rng.gen_range(-0.1f32, 0.1),
+      rng.gen_range(-0.1f32, 0.1),
+    );
+    vec![p0, p0 + d, p0 + d + e]
+  }).collect::<Vec<_>>();
+  let objects = positions.chunks(3).map( |p| {
+    Box::new(Triangle::new(p[0], p[1], p[2])) as Box<dyn Shape>
+  }).collect::<Vec<_>>();
+  let options = BuildOptions {
+    split: Split::Sbvh { budget: 0.5 },
+    ..Default::default()
+  };
+  let mut bvh = BVH::with_options(&objects, options);
+  // 予算の範囲で参照を複製し、物体分割だけの木より良くなる
+  assert!(bvh.duplicates() > 0 && bvh.duplicates() <= objects.len() / 2);
+  assert!(bvh.cost() < BVH::new(&objects).cost());
+  assert_same_as_brute_force(&objects, &bvh, &mut rng);
+  // 複製された参照があっても refit できる
+  bvh.refit(&objects);
+  assert_same_as_brute_force(&objects, &bvh, &mut rng);
+  // 予算が無ければ複製しない
+  let bvh = BVH::with_options(&objects, BuildOptions {
+    split: Split::Sbvh { budget: 0.0 },
+    ..Default::default()
+  });
+  assert_eq!(bvh.duplicates(), 0);
+  // メッシュでも面に沿って切り分ける
+  let mesh = TriangleMesh::with_options(positions, (0..3000).collect(), options);
+  assert!(mesh.hierarchy().duplicates() > 0);
+  for ray in random_ray_in_aabb(mesh.aabb(), 10000, &mut rng) {
+    let i1 = brute_force(&objects, &ray);
+    let i2 = mesh.intersect(&ray);
+    assert_eq!(i1.is_some(), i2.is_some());
+    if let (Some(i1), Some(i2)) = (i1, i2) {
+      assert!((i1.distance - i2.distance).abs() < EPS);
+    }
+  }
+}
+
+#[test]
+fn parallel_construction() {
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let objects = random_triangles(2000, &mut rng);
+  for &split in [Split::Sweep, Split::Binned(16), Split::Morton].iter() {
+    let serial = BVH::with_options(&objects, BuildOptions {
+      split,
+      parallel: false,
+      ..Default::default()
+    });
+    let parallel = BVH::with_options(&objects, BuildOptions {
+      split,
+      parallel_threshold: 64,
+      ..Default::default()
+    });
+    // 並列に構築しても全く同じ木になる
+    assert_eq!(serial.node_count(), parallel.node_count());
+    assert_eq!(serial.cost().to_bits(), parallel.cost().to_bits());
+    for ray in random_ray_in_aabb(serial.aabb(), 1000, &mut rng) {
+      let i1 = serial.intersect(&ray);
+      let i2 = parallel.intersect(&ray);
+      assert_eq!(i1.map( |v| (v.index, v.distance.to_bits()) ), i2.map( |v| (v.index, v.distance.to_bits()) ));
+    }
+  }
+}
+
+#[test]
+fn correct_random_triangles_ploc() {
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let objects = random_triangles(1000, &mut rng);
+  let bvh = BVH::with_options(&objects, BuildOptions {
+    split: Split::Ploc(16),
+    ..Default::default()
+  });
+  // SAH に従って複数のプリミティブを葉にまとめる
+  assert!(bvh.node_count() < 2 * objects.len() - 1);
+  // LBVH より質の良い木になる
+  let morton = BVH::with_options(&objects, BuildOptions {
+    split: Split::Morton,
+    ..Default::default()
+  });
+  assert!(bvh.cost() < morton.cost());
+  assert_same_as_brute_force(&objects, &bvh, &mut rng);
+  // 葉の大きさと深さの上限は他の構築方法と同じく守られる
+  let single = BVH::with_options(&objects, BuildOptions {
+    split: Split::Ploc(16),
+    max_leaf_size: 1,
+    ..Default::default()
+  });
+  assert_eq!(single.node_count(), 2 * objects.len() - 1);
+  let flat = BVH::with_options(&objects, BuildOptions {
+    split: Split::Ploc(16),
+    max_depth: 0,
+    ..Default::default()
+  });
+  assert_eq!(flat.node_count(), 1);
+  assert_same_as_brute_force(&objects, &flat, &mut rng);
+}
+
+#[test]
+#[should_panic(expected = "at least 1")]
+fn ploc_needs_positive_radius() {
+  let objects = random_triangles(10, rand::XorShiftRng::new_unseeded());
+  BVH::with_options(&objects, BuildOptions {
+    split: Split::Ploc(0),
+    ..Default::default()
+  });
+}
+
+#[test]
+fn custom_builder() {
+  // x 座標の重心順で半分に分ける分割方法
+  struct HalfX;
+  impl Partition for HalfX {
+    fn partition(&self, list: &mut [Primitive], _options: &BuildOptions) -> (AABB, usize, f32) {
+      list.sort_by( |a, b| a.aabb.center.x.partial_cmp(&b.aabb.center.x).unwrap() );
+      let aabb = list.iter().fold(AABB::empty(), |a, v| a.merge_with(&v.aabb));
+      (aabb, list.len() / 2, f32::INFINITY)
+    }
+  }
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let objects = random_triangles(1000, &mut rng);
+  let bvh = BVH::with_builder(&objects, &TopDown(HalfX), BuildOptions::default());
+  assert_same_as_brute_force(&objects, &bvh, &mut rng);
+  // 組み込みの構築方法も同じ口から使える
+  let sweep = BVH::with_builder(&objects, &TopDown(SweepSah), BuildOptions::default());
+  assert_eq!(sweep.cost().to_bits(), BVH::new(&objects).cost().to_bits());
+  assert!(sweep.cost() < bvh.cost());
+}
+
+#[test]
+#[should_panic(expected = "both sides")]
+fn custom_partition_without_split() {
+  // 全てを片側に寄せる分割方法は受け付けない
+  struct Nothing;
+  impl Partition for Nothing {
+    fn partition(&self, list: &mut [Primitive], _options: &BuildOptions) -> (AABB, usize, f32) {
+      let aabb = list.iter().fold(AABB::empty(), |a, v| a.merge_with(&v.aabb));
+      (aabb, 0, f32::INFINITY)
+    }
+  }
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let objects = random_triangles(100, &mut rng);
+  BVH::with_builder(&objects, &TopDown(Nothing), BuildOptions::default());
+}
+
+#[test]
+#[should_panic(expected = "follow the left subtree")]
+fn custom_builder_with_broken_layout() {
+  // 右の子が左の部分木の直後を指していない木は受け付けない
+  struct Broken;
+  impl Builder for Broken {
+    fn build(&self, aabbs: Vec<AABB>, _options: &BuildOptions) -> (Vec<Node>, Vec<usize>) {
+      let aabb = aabbs.iter().fold(AABB::empty(), |a, v| a.merge_with(v));
+      let node = |offset, count| Node {
+        aabb: aabb.clone(),
+        offset,
+        count,
+      };
+      (vec![node(3, 0), node(0, 1), node(1, 1), node(2, 1)], vec![0, 1, 2])
+    }
+  }
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let objects = random_triangles(3, &mut rng);
+  BVH::with_builder(&objects, &Broken, BuildOptions::default());
+}
+
+#[test]
+fn correct_random_triangles_median() {
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let objects = random_triangles(1000, &mut rng);
+  let sah = BVH::new(&objects);
+  for &split in [Split::SpatialMedian, Split::ObjectMedian].iter() {
+    let bvh = BVH::with_options(&objects, BuildOptions {
+      split,
+      ..Default::default()
+    });
+    // 比較用の基準として SAH より質が劣る
+    assert!(bvh.cost() > sah.cost());
+    assert_same_as_brute_force(&objects, &bvh, &mut rng);
+  }
+  // 個数で半分にし続けるので葉はプリミティブ1つずつになる
+  let bvh = BVH::with_options(&objects, BuildOptions {
+    split: Split::ObjectMedian,
+    max_leaf_size: 1,
+    ..Default::default()
+  });
+  assert_eq!(bvh.node_count(), 2 * objects.len() - 1);
+}
+
+#[test]
+fn presorted_sweep() {
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  // 重心が一致するものも混ぜる
+  let mut objects = random_triangles(1000, &mut rng);
+  objects.extend(random_triangles(200, rand::XorShiftRng::new_unseeded()));
+  // 深さの上限が0なら根がそのまま葉になる
+  for &(max_leaf_size, max_depth) in [(4, 64), (1, 64), (8, 6), (4, 0)].iter() {
+    let options = BuildOptions {
+      max_leaf_size,
+      max_depth,
+      ..Default::default()
+    };
+    let sweep = BVH::with_options(&objects, options);
+    let presorted = BVH::with_options(&objects, BuildOptions {
+      split: Split::PresortedSweep,
+      ..options
+    });
+    // ノードごとにソートする場合と同じ木になる
+    assert_eq!(sweep.node_count(), presorted.node_count());
+    assert_eq!(sweep.cost().to_bits(), presorted.cost().to_bits());
+    for ray in random_ray_in_aabb(sweep.aabb(), 1000, &mut rng) {
+      let i1 = sweep.intersect(&ray);
+      let i2 = presorted.intersect(&ray);
+      assert_eq!(i1.map( |v| (v.index, v.distance.to_bits()) ), i2.map( |v| (v.index, v.distance.to_bits()) ));
+    }
+    // 葉の中のプリミティブも同じ順に並ぶ
+    let aabbs = objects.iter().map( |v| v.aabb().clone() ).collect::<Vec<_>>();
+    let sweep = Hierarchy::new(aabbs.iter().cloned(), options);
+    let presorted = Hierarchy::new(aabbs.iter().cloned(), BuildOptions {
+      split: Split::PresortedSweep,
+      ..options
+    });
+    let visits = |hierarchy: &Hierarchy, ray: &Ray| {
+      let visited = RefCell::new(Vec::new());
+      hierarchy.intersect(ray, |i, _| {
+        visited.borrow_mut().push(i);
+        None
+      });
+      visited.into_inner()
+    };
+    for ray in random_ray_in_aabb(sweep.aabb(), 100, &mut rng) {
+      assert_eq!(visits(&sweep, &ray), visits(&presorted, &ray));
+    }
+  }
+}
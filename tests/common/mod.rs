@@ -0,0 +1,77 @@
+// 結合テストで共有する乱数の形状と総当たりの判定
+#![allow(dead_code)]
+
+use bvh::math::vector::*;
+use bvh::triangle::Triangle;
+use bvh::bvh::BVH;
+use bvh::shape::*;
+use bvh::ray::Ray;
+use bvh::aabb::AABB;
+use bvh::intersection::Intersection;
+use bvh::constant::EPS;
+use rand::Rng;
+
+pub fn assert_same_as_brute_force<R>(objects: &Vec<Box<dyn Shape>>, bvh: &BVH, rng: R)
+  where
+    R: Rng,
+{
+  for ray in random_ray_in_aabb(bvh.aabb(), 10000, rng) {
+    let i1 = brute_force(objects, &ray);
+    let i2 = bvh.intersect(&ray);
+    assert_eq!(i1.is_some(), i2.is_some());
+    if let Some(v) = i1 {
+      assert!((v.distance - i2.unwrap().distance).abs() < EPS);
+    }
+  }
+}
+
+pub fn brute_force(objects: &Vec<Box<dyn Shape>>, ray: &Ray) -> Option<Intersection> {
+  objects.iter().flat_map(|v| v.intersect(ray)).min_by(
+    |a, b| {
+      a.distance.partial_cmp(&b.distance).unwrap()
+    },
+  )
+}
+
+pub fn random_triangles<R>(count: usize, mut rng: R) -> Vec<Box<dyn Shape>>
+  where
+    R: Rng,
+{
+  (0..count).map( |_| {
+    let p0 = Vector3::new(
+      rng.gen_range(-10.0f32, 10.0),
+      rng.gen_range(-10.0f32, 10.0),
+      rng.gen_range(-10.0f32, 10.0),
+    );
+    let p1 = p0 + Vector3::new(
+      rng.gen_range(-1.0f32, 1.0),
+      rng.gen_range(-1.0f32, 1.0),
+      rng.gen_range(-1.0f32, 1.0),
+    );
+    let p2 = p0 + Vector3::new(
+      rng.gen_range(-1.0f32, 1.0),
+      rng.gen_range(-1.0f32, 1.0),
+      rng.gen_range(-1.0f32, 1.0),
+    );
+    let triangle: Box<dyn Shape> = Box::new(Triangle::new(p0, p1, p2));
+    triangle
+  }).collect()
+}
+
+pub fn random_ray_in_aabb<R>(aabb: &AABB, count: usize, mut rng: R) -> Vec<Ray>
+  where
+    R: Rng,
+{
+  let diagnal = (aabb.max - aabb.min).norm();
+  (0..count).map( |_| {
+    let from: Vector3 = (0..3).map( |i|
+      (aabb.max[i] - aabb.min[i]) * rng.gen_range(-1.0f32, 1.0)
+    ).collect::<Vec<_>>().into();
+    let to: Vector3 = (0..3).map( |i|
+      (aabb.max[i] - aabb.min[i]) * rng.gen_range(-1.0f32, 1.0)
+    ).collect::<Vec<_>>().into();
+    let direction = (to - from).normalize();
+    let origin = from - direction * diagnal;
+    Ray::new(origin, direction)
+  }).collect()
+}
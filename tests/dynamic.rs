@@ -0,0 +1,91 @@
+mod common;
+
+use common::*;
+use bvh::math::vector::*;
+use bvh::triangle::Triangle;
+use bvh::shape::*;
+use bvh::ray::Ray;
+use bvh::dynamic::DynamicBVH;
+use bvh::constant::*;
+
+#[test]
+fn insert_remove() {
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let mut bvh = DynamicBVH::new();
+  let mut handles = random_triangles(1000, &mut rng).into_iter().map( |v| bvh.insert(v) ).collect::<Vec<_>>();
+  // 3つに1つを削除してから追加し直す
+  for k in (0..handles.len()).rev().step_by(3) {
+    bvh.remove(handles.swap_remove(k));
+  }
+  handles.extend(random_triangles(300, &mut rng).into_iter().map( |v| bvh.insert(v) ));
+  assert_eq!(bvh.len(), handles.len());
+  assert_eq!(bvh.node_count(), 2 * handles.len() - 1);
+  for ray in random_ray_in_aabb(bvh.aabb(), 10000, &mut rng) {
+    let i1 = handles.iter().filter_map( |&h| {
+      bvh.get(h).unwrap().intersect(&ray).map( |i| (h, i) )
+    }).min_by( |a, b| a.1.distance.partial_cmp(&b.1.distance).unwrap() );
+    let i2 = bvh.intersect(&ray);
+    assert_eq!(i1.is_some(), i2.is_some());
+    assert_eq!(i1.is_some(), bvh.occluded(&ray, f32::INFINITY));
+    if let (Some((h, i1)), Some(i2)) = (i1, i2) {
+      assert!((i1.distance - i2.distance).abs() < EPS);
+      assert_eq!(Some(h), bvh.handle(i2.index));
+    }
+  }
+  for &h in &handles {
+    assert!(bvh.remove(h).is_some());
+  }
+  assert!(bvh.is_empty() && bvh.intersect(&Ray::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0))).is_none());
+  // 削除済みのハンドルは位置が再利用されても別の形状を指さない
+  let stale = *handles.last().unwrap();
+  let fresh = random_triangles(1, &mut rng).into_iter().map( |v| bvh.insert(v) ).next().unwrap();
+  assert_eq!(stale.index(), fresh.index());
+  assert!(bvh.get(stale).is_none() && bvh.remove(stale).is_none());
+  assert!(bvh.get(fresh).is_some());
+}
+
+#[test]
+fn sorted_insert() {
+  // 整列済みで一直線に並んだ形状を挿入しても木が偏らない
+  let count = 100000;
+  let mut bvh = DynamicBVH::new();
+  for i in 0..count {
+    let x = i as f32;
+    bvh.insert(Box::new(Triangle::new(
+      Vector3::new(x, 0.0, 0.0),
+      Vector3::new(x + 0.5, 0.0, 0.0),
+      Vector3::new(x, 0.5, 0.0),
+    )));
+  }
+  // 均衡した木のコストは深さ (log2 n ≒ 17) 程度
+  assert!(bvh.cost() < 100.0, "cost: {}", bvh.cost());
+  let ray = Ray::new(Vector3::new(count as f32 - 0.9, 0.1, -1.0), Vector3::new(0.0, 0.0, 1.0));
+  let hit = bvh.intersect(&ray).unwrap();
+  assert_eq!(hit.index, count - 1);
+  assert!(bvh.occluded(&ray, f32::INFINITY));
+}
+
+#[test]
+fn optimize() {
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  // 挿入で作った木も回転で改善し、交差結果は変わらない
+  let mut dynamic = DynamicBVH::new();
+  let handles = random_triangles(1000, &mut rng).into_iter().map( |v| dynamic.insert(v) ).collect::<Vec<_>>();
+  let (before, after) = dynamic.optimize();
+  assert!(after <= before);
+  assert_eq!(dynamic.node_count(), 2 * handles.len() - 1);
+  for ray in random_ray_in_aabb(dynamic.aabb(), 10000, &mut rng) {
+    let i1 = handles.iter().filter_map( |&h| dynamic.get(h).unwrap().intersect(&ray) )
+      .min_by( |a, b| a.distance.partial_cmp(&b.distance).unwrap() );
+    let i2 = dynamic.intersect(&ray);
+    assert_eq!(i1.is_some(), i2.is_some());
+    if let (Some(i1), Some(i2)) = (i1, i2) {
+      assert!((i1.distance - i2.distance).abs() < EPS);
+    }
+  }
+  // 削除後も親子関係が保たれている
+  for h in handles {
+    dynamic.remove(h);
+  }
+  assert!(dynamic.is_empty());
+}
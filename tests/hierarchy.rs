@@ -0,0 +1,174 @@
+mod common;
+
+use common::*;
+use bvh::math::vector::*;
+use bvh::triangle::Triangle;
+use bvh::bvh::{BVH, BuildOptions};
+use bvh::shape::*;
+use bvh::ray::Ray;
+use bvh::mesh::TriangleMesh;
+use bvh::scene::Scene;
+use bvh::constant::*;
+use rand::Rng;
+
+#[test]
+fn correct_random_triangles() {
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let objects = random_triangles(1000, &mut rng);
+  let bvh = BVH::new(&objects);
+  assert_same_as_brute_force(&objects, &bvh, &mut rng);
+}
+
+#[test]
+fn correct_random_triangles_leaf() {
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let objects = random_triangles(1000, &mut rng);
+  let bvh = BVH::with_options(&objects, BuildOptions {
+    intersection_cost: 4.0,
+    max_leaf_size: 8,
+    max_depth: 8,
+    ..Default::default()
+  });
+  assert_same_as_brute_force(&objects, &bvh, &mut rng);
+}
+
+#[test]
+fn multi_primitive_leaf() {
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let objects = random_triangles(1000, &mut rng);
+  let single = BVH::with_options(&objects, BuildOptions {
+    max_leaf_size: 1,
+    ..Default::default()
+  });
+  let bvh = BVH::new(&objects);
+  assert_eq!(single.node_count(), 2 * objects.len() - 1);
+  assert!(bvh.node_count() < single.node_count());
+  assert_same_as_brute_force(&objects, &bvh, &mut rng);
+}
+
+#[test]
+fn occluded() {
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let objects = random_triangles(1000, &mut rng);
+  let bvh = BVH::new(&objects);
+  for ray in random_ray_in_aabb(bvh.aabb(), 10000, &mut rng) {
+    let t_max = rng.gen_range(0.0f32, 60.0);
+    let expected = brute_force(&objects, &ray).is_some_and(|i| i.distance < t_max);
+    assert_eq!(bvh.occluded(&ray, t_max), expected);
+  }
+}
+
+#[test]
+fn ray_interval() {
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let objects = random_triangles(1000, &mut rng);
+  let bvh = BVH::new(&objects);
+  for ray in random_ray_in_aabb(bvh.aabb(), 10000, &mut rng) {
+    let t_min = rng.gen_range(0.0f32, 40.0);
+    let t_max = t_min + rng.gen_range(0.0f32, 20.0);
+    let ray = Ray::with_interval(ray.origin, ray.direction, t_min, t_max);
+    let i1 = brute_force(&objects, &ray);
+    let i2 = bvh.intersect(&ray);
+    assert_eq!(i1.is_some(), i2.is_some());
+    if let Some(v) = i2 {
+      assert!(t_min <= v.distance && v.distance <= t_max);
+      assert!((v.distance - i1.unwrap().distance).abs() < EPS);
+    }
+  }
+}
+
+#[test]
+fn far_geometry() {
+  let objects: Vec<Box<dyn Shape>> = vec![Box::new(Triangle::new(
+    Vector3::new(-1.0, -1.0, 1e6),
+    Vector3::new(1.0, -1.0, 1e6),
+    Vector3::new(0.0, 1.0, 1e6),
+  ))];
+  let bvh = BVH::new(&objects);
+  let ray = Ray::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0));
+  assert_eq!(bvh.intersect(&ray).map( |i| i.distance ), Some(1e6));
+}
+
+#[test]
+fn primitive_index_and_barycentric() {
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let objects = random_triangles(1000, &mut rng);
+  let bvh = BVH::new(&objects);
+  for ray in random_ray_in_aabb(bvh.aabb(), 10000, &mut rng) {
+    if let Some(i) = bvh.intersect(&ray) {
+      let expected = objects[i.index].intersect(&ray).unwrap();
+      assert_eq!(i.distance, expected.distance);
+      assert_eq!((i.u, i.v), (expected.u, expected.v));
+    }
+  }
+  let triangle = Triangle::new(
+    Vector3::new(0.0, 0.0, 1.0),
+    Vector3::new(1.0, 0.0, 1.0),
+    Vector3::new(0.0, 1.0, 1.0),
+  );
+  let ray = Ray::new(Vector3::new(0.25, 0.5, 0.0), Vector3::new(0.0, 0.0, 1.0));
+  let i = triangle.intersect(&ray).unwrap();
+  assert!((i.u - 0.25).abs() < EPS && (i.v - 0.5).abs() < EPS);
+}
+
+#[test]
+fn empty() {
+  let ray = Ray::new(Vector3::zero(), Vector3::new(0.0, 0.0, 1.0));
+  let scene = Scene::new();
+  assert!(scene.intersect(&ray).is_none() && !scene.occluded(&ray, f32::INFINITY));
+  let objects: Vec<Box<dyn Shape>> = Vec::new();
+  let mut bvh = BVH::new(&objects);
+  assert!(bvh.intersect(&ray).is_none() && !bvh.occluded(&ray, f32::INFINITY));
+  assert_eq!(bvh.optimize(), (0.0, 0.0));
+  let mesh = TriangleMesh::new(Vec::new(), Vec::new());
+  assert!(mesh.intersect(&ray).is_none() && !mesh.occluded(&ray, f32::INFINITY));
+}
+
+#[test]
+fn refit() {
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  // 別の配置の三角形に差し替えて refit しても総当たりと一致する
+  let objects = random_triangles(1000, &mut rng);
+  let moved = random_triangles(1000, &mut rng);
+  let mut bvh = BVH::new(&objects);
+  let node_count = bvh.node_count();
+  bvh.refit(&moved);
+  assert_eq!(bvh.node_count(), node_count);
+  assert_same_as_brute_force(&moved, &bvh, &mut rng);
+  // メッシュの頂点を動かして refit したものは作り直したものと同じ交差を返す
+  let positions = (0..600).map( |_| Vector3::new(
+    rng.gen_range(-10.0f32, 10.0),
+    rng.gen_range(-10.0f32, 10.0),
+    rng.gen_range(-10.0f32, 10.0),
+  )).collect::<Vec<_>>();
+  let indices = (0..600).collect::<Vec<u32>>();
+  let mut mesh = TriangleMesh::new(positions, indices.clone());
+  for p in mesh.positions_mut() {
+    *p = *p * 0.5 + Vector3::new(rng.gen_range(-3.0f32, 3.0), 0.0, 0.0);
+  }
+  mesh.refit();
+  let rebuilt = TriangleMesh::new(mesh.positions().to_vec(), indices);
+  for ray in random_ray_in_aabb(rebuilt.aabb(), 10000, &mut rng) {
+    let i1 = rebuilt.intersect(&ray);
+    let i2 = mesh.intersect(&ray);
+    assert_eq!(i1.is_some(), i2.is_some());
+    if let (Some(i1), Some(i2)) = (i1, i2) {
+      assert!((i1.distance - i2.distance).abs() < EPS);
+      assert_eq!(i1.index, i2.index);
+    }
+  }
+}
+
+#[test]
+fn optimize() {
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  // 別の配置に refit して質が落ちた木を回転で改善する
+  let objects = random_triangles(1000, &mut rng);
+  let moved = random_triangles(1000, &mut rng);
+  let mut bvh = BVH::new(&objects);
+  bvh.refit(&moved);
+  let (before, after) = bvh.optimize();
+  assert!(after < before);
+  assert!((bvh.cost() - after).abs() < EPS * after);
+  assert_same_as_brute_force(&moved, &bvh, &mut rng);
+}
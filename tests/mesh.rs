@@ -0,0 +1,77 @@
+mod common;
+
+use common::*;
+use bvh::math::vector::*;
+use bvh::triangle::Triangle;
+use bvh::shape::*;
+use bvh::ray::Ray;
+use bvh::mesh::TriangleMesh;
+use bvh::constant::*;
+use rand::Rng;
+
+#[test]
+fn intersect() {
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let positions = (0..500).map( |_| Vector3::new(
+    rng.gen_range(-10.0f32, 10.0),
+    rng.gen_range(-10.0f32, 10.0),
+    rng.gen_range(-10.0f32, 10.0),
+  )).collect::<Vec<_>>();
+  let indices = (0..300).flat_map( |_| {
+    let i = rng.gen_range(0, positions.len() as u32 - 2);
+    vec![i, i + 1, i + 2]
+  }).collect::<Vec<_>>();
+  let objects = indices.chunks(3).map( |f| {
+    let triangle: Box<dyn Shape> = Box::new(Triangle::new(
+      positions[f[0] as usize],
+      positions[f[1] as usize],
+      positions[f[2] as usize],
+    ));
+    triangle
+  }).collect::<Vec<_>>();
+  let mesh = TriangleMesh::new(positions, indices);
+  for ray in random_ray_in_aabb(mesh.aabb(), 10000, &mut rng) {
+    let i1 = brute_force(&objects, &ray);
+    let i2 = mesh.intersect(&ray);
+    assert_eq!(i1.is_some(), i2.is_some());
+    if let Some(v) = i2 {
+      assert!((v.distance - i1.unwrap().distance).abs() < EPS);
+      assert_eq!(objects[v.index].intersect(&ray).map( |i| i.distance ), Some(v.distance));
+    }
+    assert_eq!(mesh.occluded(&ray, 30.0), objects.iter().any( |v| v.occluded(&ray, 30.0) ));
+  }
+}
+
+#[test]
+#[should_panic(expected = "existing vertices")]
+fn index_out_of_range() {
+  let positions = vec![Vector3::zero(), Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0)];
+  TriangleMesh::new(positions, vec![0, 1, 3]);
+}
+
+#[test]
+fn attributes() {
+  let positions = vec![
+    Vector3::new(0.0, 0.0, 1.0),
+    Vector3::new(1.0, 0.0, 1.0),
+    Vector3::new(0.0, 1.0, 1.0),
+  ];
+  let normals = vec![
+    Vector3::new(0.0, 0.0, -1.0),
+    Vector3::new(1.0, 0.0, 0.0),
+    Vector3::new(0.0, 1.0, 0.0),
+  ];
+  let texcoords = vec![(0.0, 0.0), (1.0, 0.0), (0.0, 2.0)];
+  let ray = Ray::new(Vector3::new(0.25, 0.5, 0.0), Vector3::new(0.0, 0.0, 1.0));
+  let flat = TriangleMesh::new(positions.clone(), vec![0, 1, 2]);
+  let i = flat.intersect(&ray).unwrap();
+  assert!((i.shading_normal - i.normal).norm() < EPS);
+  let mesh = TriangleMesh::new(positions, vec![0, 1, 2])
+    .with_normals(normals)
+    .with_texcoords(texcoords);
+  let i = mesh.intersect(&ray).unwrap();
+  let expected = Vector3::new(0.25, 0.5, -0.25).normalize();
+  assert!((i.shading_normal - expected).norm() < EPS);
+  assert!((i.normal - Vector3::new(0.0, 0.0, 1.0)).norm() < EPS);
+  assert!((i.texcoord.0 - 0.25).abs() < EPS && (i.texcoord.1 - 1.0).abs() < EPS);
+}
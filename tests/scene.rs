@@ -0,0 +1,136 @@
+mod common;
+
+use common::*;
+use bvh::math::vector::*;
+use bvh::math::matrix::*;
+use bvh::triangle::Triangle;
+use bvh::bvh::BVH;
+use bvh::shape::*;
+use bvh::mesh::TriangleMesh;
+use bvh::sphere::Sphere;
+use bvh::instance::Instance;
+use bvh::scene::Scene;
+use bvh::constant::*;
+use rand::Rng;
+use std::sync::Arc;
+
+#[test]
+fn instance() {
+  // 回転・拡大・平行移動した単位球は、対応する球と同じ交差を返す
+  let center = Vector3::new(1.0, 2.0, 3.0);
+  let transform = Matrix4::translate(center)
+    * Matrix4::axis_angle(Vector3::new(0.0, 1.0, 0.0), 0.7)
+    * Matrix4::scale(Vector3::new(2.0, 2.0, 2.0));
+  let inverse = transform.inverse().unwrap();
+  let p = Vector3::new(0.3, -0.2, 0.5);
+  assert!((inverse.transform_point(transform.transform_point(p)) - p).norm() < EPS);
+  // 潰れた変換は逆行列を持たないので配置できない
+  let singular = Matrix4::scale(Vector3::new(1.0, 0.0, 1.0));
+  assert!(singular.inverse().is_none());
+  assert!(Instance::new(Sphere::new(Vector3::zero(), 1.0), singular).is_none());
+  let instance = Instance::new(Sphere::new(Vector3::zero(), 1.0), transform).unwrap();
+  let sphere = Sphere::new(center, 2.0);
+  // 回転した箱を囲むので球そのものの AABB を含む
+  for k in 0..3 {
+    assert!(instance.aabb().min[k] <= sphere.aabb().min[k] + 1e-3);
+    assert!(instance.aabb().max[k] >= sphere.aabb().max[k] - 1e-3);
+  }
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  for ray in random_ray_in_aabb(sphere.aabb(), 10000, &mut rng) {
+    let i1 = sphere.intersect(&ray);
+    let i2 = instance.intersect(&ray);
+    assert_eq!(i1.is_some(), i2.is_some());
+    if let (Some(i1), Some(i2)) = (i1, i2) {
+      assert!((i1.distance - i2.distance).abs() < 1e-3);
+      assert!((i1.position - i2.position).norm() < 1e-3);
+      assert!((i1.normal - i2.normal).norm() < 1e-3);
+    }
+  }
+  // 1つのメッシュを共有する非一様スケールのインスタンス群
+  let positions = (0..300).map( |_| Vector3::new(
+    rng.gen_range(-1.0f32, 1.0),
+    rng.gen_range(-1.0f32, 1.0),
+    rng.gen_range(-1.0f32, 1.0),
+  )).collect::<Vec<_>>();
+  let indices = (0..300).collect::<Vec<u32>>();
+  let mesh = Arc::new(TriangleMesh::new(positions, indices));
+  let objects = (0..50).map( |_| {
+    let transform = Matrix4::translate(Vector3::new(
+      rng.gen_range(-10.0f32, 10.0),
+      rng.gen_range(-10.0f32, 10.0),
+      rng.gen_range(-10.0f32, 10.0),
+    )) * Matrix4::axis_angle(Vector3::new(1.0, 1.0, 0.0).normalize(), rng.gen_range(0.0f32, PI))
+      * Matrix4::scale(Vector3::new(1.0, rng.gen_range(0.5f32, 2.0), 3.0));
+    let instance: Box<dyn Shape> = Box::new(Instance::new(mesh.clone(), transform).unwrap());
+    instance
+  }).collect::<Vec<_>>();
+  let bvh = BVH::new(&objects);
+  assert_same_as_brute_force(&objects, &bvh, &mut rng);
+}
+
+#[test]
+fn scene() {
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let meshes = (0..2).map( |_| {
+    let positions = (0..150).map( |_| Vector3::new(
+      rng.gen_range(-1.0f32, 1.0),
+      rng.gen_range(-1.0f32, 1.0),
+      rng.gen_range(-1.0f32, 1.0),
+    )).collect::<Vec<_>>();
+    (positions, (0..150).collect::<Vec<u32>>())
+  }).collect::<Vec<_>>();
+  let mut scene = Scene::new();
+  let ids = meshes.iter().map( |(p, i)| {
+    scene.add_geometry(TriangleMesh::new(p.clone(), i.clone()))
+  }).collect::<Vec<_>>();
+  fn random_transform<R: Rng>(rng: &mut R) -> Matrix4 {
+    Matrix4::translate(Vector3::new(
+      rng.gen_range(-10.0f32, 10.0),
+      rng.gen_range(-10.0f32, 10.0),
+      rng.gen_range(-10.0f32, 10.0),
+    )) * Matrix4::axis_angle(Vector3::new(0.0, 0.0, 1.0), rng.gen_range(0.0f32, PI))
+  }
+  let mut transforms = (0..40).map( |_| random_transform(&mut rng) ).collect::<Vec<_>>();
+  for (k, transform) in transforms.iter().enumerate() {
+    assert_eq!(scene.add_instance(ids[k % 2], transform.clone()), Some(k));
+  }
+  // 潰れた変換は受け付けず、既存の配置も変えない
+  let singular = Matrix4::scale(Vector3::new(0.0, 1.0, 1.0));
+  assert!(scene.add_instance(ids[0], singular.clone()).is_none());
+  assert!(scene.set_transform(0, singular).is_none());
+  assert_eq!(scene.instance_count(), 40);
+  // インスタンスを動かして上位の階層だけ作り直しても正しい
+  for step in 0..2 {
+    if step == 1 {
+      for k in (0..40).step_by(3) {
+        transforms[k] = random_transform(&mut rng);
+        scene.set_transform(k, transforms[k].clone()).unwrap();
+      }
+    }
+    scene.build();
+    // 総当たり用にワールド座標の三角形を (インスタンス ID, プリミティブ ID) 順に並べる
+    let objects = transforms.iter().enumerate().flat_map( |(k, transform)| {
+      let (ref p, ref i) = meshes[k % 2];
+      i.chunks(3).map( |f| {
+        let triangle: Box<dyn Shape> = Box::new(Triangle::new(
+          transform.transform_point(p[f[0] as usize]),
+          transform.transform_point(p[f[1] as usize]),
+          transform.transform_point(p[f[2] as usize]),
+        ));
+        triangle
+      }).collect::<Vec<_>>()
+    }).collect::<Vec<_>>();
+    for ray in random_ray_in_aabb(scene.aabb(), 10000, &mut rng) {
+      let i1 = objects.iter().enumerate().filter_map( |(k, v)| {
+        v.intersect(&ray).map( |i| (k, i) )
+      }).min_by( |a, b| a.1.distance.partial_cmp(&b.1.distance).unwrap() );
+      let i2 = scene.intersect(&ray);
+      assert_eq!(i1.is_some(), i2.is_some());
+      assert_eq!(i1.is_some(), scene.occluded(&ray, f32::INFINITY));
+      if let (Some((k, i1)), Some(i2)) = (i1, i2) {
+        assert!((i1.distance - i2.distance).abs() < 1e-3);
+        assert_eq!((i2.instance, i2.index), (k / 50, k % 50));
+      }
+    }
+  }
+}
@@ -0,0 +1,95 @@
+mod common;
+
+use common::*;
+use bvh::math::vector::*;
+use bvh::bvh::BVH;
+use bvh::shape::*;
+use bvh::ray::Ray;
+use bvh::sphere::Sphere;
+use bvh::quad::Quad;
+use bvh::disk::Disk;
+use bvh::cylinder::Cylinder;
+use bvh::capsule::Capsule;
+use bvh::constant::*;
+use rand::Rng;
+
+#[test]
+fn sphere() {
+  let sphere = Sphere::new(Vector3::new(0.0, 0.0, 1000.0), 1.0);
+  let ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+  let i = sphere.intersect(&ray).unwrap();
+  assert!((i.distance - 999.0).abs() < EPS);
+  assert!((i.normal - Vector3::new(0.0, 0.0, -1.0)).norm() < EPS);
+  // 内部からは奥の面に当たる
+  let inside = Ray::new(sphere.center, Vector3::new(0.0, 1.0, 0.0));
+  assert!((sphere.intersect(&inside).unwrap().distance - 1.0).abs() < EPS);
+  let miss = Ray::new(Vector3::new(0.0, 1.001, 0.0), Vector3::new(0.0, 0.0, 1.0));
+  assert!(sphere.intersect(&miss).is_none());
+  // 三角形と混在させても総当たりと一致する
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let mut objects = random_triangles(500, &mut rng);
+  for _ in 0..500 {
+    let center = Vector3::new(
+      rng.gen_range(-10.0f32, 10.0),
+      rng.gen_range(-10.0f32, 10.0),
+      rng.gen_range(-10.0f32, 10.0),
+    );
+    objects.push(Box::new(Sphere::new(center, rng.gen_range(0.1f32, 1.0))));
+  }
+  let bvh = BVH::new(&objects);
+  assert_same_as_brute_force(&objects, &bvh, &mut rng);
+}
+
+#[test]
+fn analytic_shapes() {
+  let z = Vector3::new(0.0, 0.0, 1.0);
+  let x = Vector3::new(1.0, 0.0, 0.0);
+  let quad = Quad::new(Vector3::new(0.0, 0.0, 1.0), Vector3::new(2.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+  let i = quad.intersect(&Ray::new(Vector3::new(1.5, 0.5, 0.0), z)).unwrap();
+  assert!((i.distance - 1.0).abs() < EPS && (i.u - 0.75).abs() < EPS && (i.v - 0.5).abs() < EPS);
+  assert!(quad.intersect(&Ray::new(Vector3::new(2.5, 0.5, 0.0), z)).is_none());
+  let disk = Disk::new(Vector3::new(0.0, 0.0, 5.0), -z, 1.0);
+  let i = disk.intersect(&Ray::new(Vector3::zero(), z)).unwrap();
+  assert!((i.distance - 5.0).abs() < EPS && (i.normal + z).norm() < EPS && i.v.abs() < EPS);
+  assert!(disk.intersect(&Ray::new(Vector3::new(1.1, 0.0, 0.0), z)).is_none());
+  let cylinder = Cylinder::new(Vector3::zero(), Vector3::new(0.0, 0.0, 2.0), 1.0);
+  let i = cylinder.intersect(&Ray::new(Vector3::new(-5.0, 0.0, 1.0), x)).unwrap();
+  assert!((i.distance - 4.0).abs() < EPS && (i.normal + x).norm() < EPS && (i.v - 0.5).abs() < EPS);
+  assert!(cylinder.intersect(&Ray::new(Vector3::new(-5.0, 0.0, 3.0), x)).is_none());
+  let i = cylinder.intersect(&Ray::new(Vector3::new(0.0, 0.0, 1.0), x)).unwrap();
+  assert!((i.distance - 1.0).abs() < EPS);
+  let capsule = Capsule::new(Vector3::zero(), Vector3::new(0.0, 0.0, 2.0), 1.0);
+  let i = capsule.intersect(&Ray::new(Vector3::new(0.0, 0.0, -5.0), z)).unwrap();
+  assert!((i.distance - 4.0).abs() < EPS && (i.normal + z).norm() < EPS && i.v.abs() < EPS);
+  let i = capsule.intersect(&Ray::new(Vector3::new(-5.0, 0.0, 1.0), x)).unwrap();
+  assert!((i.distance - 4.0).abs() < EPS && (i.normal + x).norm() < EPS);
+  let i = capsule.intersect(&Ray::new(Vector3::new(0.0, 0.0, 1.0), z)).unwrap();
+  assert!((i.distance - 2.0).abs() < EPS && (i.normal - z).norm() < EPS);
+  // 全ての形状を混在させたBVHが総当たりと一致し、交差点は各AABBに収まる
+  let mut rng = rand::XorShiftRng::new_unseeded();
+  let mut objects: Vec<Box<dyn Shape>> = Vec::new();
+  for _ in 0..200 {
+    let mut v = || Vector3::new(
+      rng.gen_range(-10.0f32, 10.0),
+      rng.gen_range(-10.0f32, 10.0),
+      rng.gen_range(-10.0f32, 10.0),
+    );
+    let (p, a, b) = (v(), v() / 10.0, v() / 10.0);
+    objects.push(Box::new(Quad::new(p, a, b)));
+    objects.push(Box::new(Disk::new(p, a, b.norm())));
+    objects.push(Box::new(Cylinder::new(p, p + a, b.norm() / 2.0)));
+    objects.push(Box::new(Capsule::new(p, p + b, a.norm() / 2.0)));
+  }
+  let bvh = BVH::new(&objects);
+  for ray in random_ray_in_aabb(bvh.aabb(), 10000, &mut rng) {
+    for object in &objects {
+      if let Some(i) = object.intersect(&ray) {
+        let aabb = object.aabb();
+        for k in 0..3 {
+          assert!(aabb.min[k] - EPS * 10.0 <= i.position[k] && i.position[k] <= aabb.max[k] + EPS * 10.0);
+        }
+      }
+    }
+  }
+  assert_same_as_brute_force(&objects, &bvh, &mut rng);
+}